@@ -0,0 +1,209 @@
+use macroquad::prelude::{is_key_down, is_key_pressed, KeyCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Keys that can be bound, saved by their `Debug` name
+const KEYS: [KeyCode; 52] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::LeftShift,
+    KeyCode::RightShift,
+    KeyCode::LeftControl,
+    KeyCode::RightControl,
+    KeyCode::LeftAlt,
+    KeyCode::RightAlt,
+    KeyCode::KpEnter,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    LeftUp,
+    LeftDown,
+    RightUp,
+    RightDown,
+    MenuUp,
+    MenuDown,
+    Enter,
+    Escape,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::LeftUp,
+        Action::LeftDown,
+        Action::RightUp,
+        Action::RightDown,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::Enter,
+        Action::Escape,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::LeftUp => "left up",
+            Action::LeftDown => "left down",
+            Action::RightUp => "right up",
+            Action::RightDown => "right down",
+            Action::MenuUp => "menu up",
+            Action::MenuDown => "menu down",
+            Action::Enter => "select",
+            Action::Escape => "back",
+        }
+    }
+
+    fn default_key(&self) -> KeyCode {
+        match self {
+            Action::LeftUp => KeyCode::W,
+            Action::LeftDown => KeyCode::S,
+            Action::RightUp | Action::MenuUp => KeyCode::Up,
+            Action::RightDown | Action::MenuDown => KeyCode::Down,
+            Action::Enter => KeyCode::Enter,
+            Action::Escape => KeyCode::Escape,
+        }
+    }
+}
+
+/// Key for each action, by key name; unknown names fall back to the default key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    keys: BTreeMap<Action, String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            keys: Action::ALL
+                .iter()
+                .map(|action| (*action, key_name(action.default_key())))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys
+            .get(&action)
+            .and_then(|name| key_from_name(name))
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    /// Bind `key` to `action`, returning false for keys that can not be bound
+    pub fn bind(&mut self, action: Action, key: KeyCode) -> bool {
+        if !KEYS.contains(&key) {
+            return false;
+        }
+        self.keys.insert(action, key_name(key));
+        true
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        is_key_down(self.key(action))
+    }
+
+    pub fn is_pressed(&self, action: Action) -> bool {
+        is_key_pressed(self.key(action))
+    }
+
+    /// Short text for the key bound to `action`, for key glyphs
+    pub fn label(&self, action: Action) -> String {
+        match self.key(action) {
+            KeyCode::Up => "^".to_string(),
+            KeyCode::Down => "v".to_string(),
+            KeyCode::Left => "<".to_string(),
+            KeyCode::Right => ">".to_string(),
+            key => key_name(key).trim_start_matches("Key").to_lowercase(),
+        }
+    }
+}
+
+fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().copied().find(|key| key_name(*key) == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip() {
+        let mut bindings = KeyBindings::default();
+        assert!(bindings.bind(Action::LeftUp, KeyCode::Key1));
+        assert!(bindings.bind(Action::Enter, KeyCode::KpEnter));
+        let json = serde_json::to_string(&bindings).unwrap();
+        let loaded: KeyBindings = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, bindings);
+        assert_eq!(loaded.key(Action::LeftUp), KeyCode::Key1);
+        assert_eq!(loaded.key(Action::Enter), KeyCode::KpEnter);
+        assert_eq!(loaded.key(Action::LeftDown), KeyCode::S);
+    }
+
+    #[test]
+    fn unknown_or_missing_keys_use_defaults() {
+        let json = r#"{"keys": {"left_up": "Key7", "left_down": "NoSuchKey"}}"#;
+        let bindings: KeyBindings = serde_json::from_str(json).unwrap();
+        assert_eq!(bindings.key(Action::LeftUp), KeyCode::Key7);
+        assert_eq!(bindings.key(Action::LeftDown), KeyCode::S);
+        assert_eq!(bindings.key(Action::Escape), KeyCode::Escape);
+
+        let empty: KeyBindings = serde_json::from_str("{}").unwrap();
+        assert_eq!(empty, KeyBindings::default());
+    }
+
+    #[test]
+    fn bind_rejects_unlisted_keys() {
+        let mut bindings = KeyBindings::default();
+        assert!(!bindings.bind(Action::LeftUp, KeyCode::F1));
+        assert_eq!(bindings.key(Action::LeftUp), KeyCode::W);
+    }
+}
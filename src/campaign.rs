@@ -0,0 +1,187 @@
+use crate::{config, resources::load_asset, state::GameState, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    path::PathBuf,
+};
+
+pub const CAMPAIGN_FILE: &str = "campaign.json";
+const PROGRESS_FILE: &str = "campaign_progress.json";
+
+/// Ordered set of levels played one after another
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Campaign {
+    pub name: String,
+    pub levels: Vec<CampaignLevel>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CampaignLevel {
+    pub name: String,
+    pub level_file: String,
+    #[serde(default)]
+    pub unlock: Unlock,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unlock {
+    #[default]
+    Always,
+    /// Unlocked once the given level file has been completed
+    Completed(String),
+    /// Unlocked once a campaign has been finished with at least this score
+    Score(u16),
+}
+
+/// Completed levels and best score, persisted in the user data directory
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CampaignProgress {
+    pub completed: BTreeSet<String>,
+    pub best_score: u16,
+}
+
+impl Campaign {
+    pub fn load(file_name: &str) -> Result<Self> {
+        let data =
+            load_asset(file_name).ok_or(format!("Could not load campaign \"{}\"", file_name))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    pub fn is_unlocked(&self, index: usize, progress: &CampaignProgress) -> bool {
+        match self.levels.get(index).map(|level| &level.unlock) {
+            Some(Unlock::Always) => true,
+            Some(Unlock::Completed(level_file)) => progress.completed.contains(level_file),
+            Some(Unlock::Score(score)) => progress.best_score >= *score,
+            None => false,
+        }
+    }
+
+    /// Load the level at `index`, tagged with its campaign position
+    pub fn load_level(&self, index: usize) -> Result<GameState> {
+        let level = self
+            .levels
+            .get(index)
+            .ok_or(format!("Campaign has no level {}", index))?;
+        let mut game = GameState::load(&level.level_file)?;
+        game.progress.campaign_level = Some(index);
+        Ok(game)
+    }
+}
+
+impl CampaignProgress {
+    /// Load saved progress, starting over if there is none
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or("No user data directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer(&file, self)?;
+        Ok(())
+    }
+
+    fn path() -> Option<PathBuf> {
+        config::user_data_dir().map(|dir| dir.join(PROGRESS_FILE))
+    }
+}
+
+/// Record a completed campaign level and return the next one,
+/// carrying over score and remaining balls
+pub fn complete_level(game: &GameState) -> Result<Option<GameState>> {
+    let index = match game.progress.campaign_level {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    let campaign = Campaign::load(CAMPAIGN_FILE)?;
+    let mut progress = CampaignProgress::load();
+    progress.completed.insert(game.level.level_file.to_string());
+
+    let next = if index + 1 < campaign.levels.len() {
+        Some(campaign.load_level(index + 1)?.with_progress_from(game))
+    } else {
+        progress.best_score = u16::max(progress.best_score, game.progress.score);
+        None
+    };
+    progress.save()?;
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::LevelResult;
+
+    fn level(level_file: &str, unlock: Unlock) -> CampaignLevel {
+        CampaignLevel {
+            name: level_file.to_string(),
+            level_file: level_file.to_string(),
+            unlock,
+        }
+    }
+
+    #[test]
+    fn levels_unlock_in_order() {
+        let campaign = Campaign {
+            name: "test".to_string(),
+            levels: vec![
+                level("level_a.json", Unlock::Always),
+                level(
+                    "level_b.json",
+                    Unlock::Completed("level_a.json".to_string()),
+                ),
+                level("level_c.json", Unlock::Score(100)),
+            ],
+        };
+        let mut progress = CampaignProgress::default();
+        let unlocked = |progress: &CampaignProgress| {
+            (0..4)
+                .map(|index| campaign.is_unlocked(index, progress))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(unlocked(&progress), [true, false, false, false]);
+        progress.completed.insert("level_a.json".to_string());
+        assert_eq!(unlocked(&progress), [true, true, false, false]);
+        progress.best_score = 100;
+        assert_eq!(unlocked(&progress), [true, true, true, false]);
+    }
+
+    #[test]
+    fn shipped_campaign_loads_every_level() {
+        let campaign = Campaign::load(CAMPAIGN_FILE).unwrap();
+        assert!(campaign.levels.len() >= 2);
+        assert!(campaign.is_unlocked(0, &CampaignProgress::default()));
+        assert!(!campaign.is_unlocked(1, &CampaignProgress::default()));
+        for index in 0..campaign.levels.len() {
+            let game = campaign.load_level(index).unwrap();
+            assert_eq!(game.progress.campaign_level, Some(index));
+        }
+    }
+
+    #[test]
+    fn next_level_carries_progress() {
+        let campaign = Campaign::load(CAMPAIGN_FILE).unwrap();
+        let mut first = campaign.load_level(0).unwrap();
+        first.progress.score = 420;
+        first.progress.balls_left = 2;
+        first.progress.results = vec![LevelResult {
+            level_file: first.level.level_file.to_string(),
+            name: first.level.name.to_string(),
+            score: 420,
+        }];
+        let next = campaign.load_level(1).unwrap().with_progress_from(&first);
+        assert_eq!(next.progress.campaign_level, Some(1));
+        assert_eq!(next.progress.score, 420);
+        assert_eq!(next.progress.start_score, 420);
+        assert_eq!(next.progress.balls_left, 2);
+        assert_eq!(next.progress.results, first.progress.results);
+    }
+}
@@ -1,5 +1,6 @@
 use macroquad::miniquad::conf::Conf as Window;
 use macroquad::prelude::*;
+use std::{env, path::PathBuf};
 
 pub const SCALE: f32 = 1080.0; // 1m in pixels
 pub const WINDOW_W: i32 = 540;
@@ -8,6 +9,36 @@ pub const SCREEN_W: f32 = 1080.0;
 pub const SCREEN_H: f32 = 1920.0;
 const GAME_NAME: &str = "Arcade Game";
 
+#[derive(Debug, Default)]
+pub struct Args {
+    pub assets: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub trace: Option<String>,
+    pub prove: Option<String>,
+    /// Input source specs, replacing the players in the settings
+    pub players: Vec<String>,
+}
+
+impl Args {
+    pub fn parse() -> Self {
+        let mut args = Args::default();
+        let mut argv = env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--assets" => args.assets = argv.next(),
+                "--record" => args.record = argv.next(),
+                "--replay" => args.replay = argv.next(),
+                "--trace" => args.trace = argv.next(),
+                "--prove" => args.prove = argv.next(),
+                "--player" => args.players.extend(argv.next()),
+                _ => eprintln!("Unknown argument \"{}\"", arg),
+            }
+        }
+        args
+    }
+}
+
 pub fn window_conf() -> Window {
     Window {
         window_title: GAME_NAME.to_owned(),
@@ -16,3 +47,12 @@ pub fn window_conf() -> Window {
         ..Default::default()
     }
 }
+
+/// Per-user directory for levels and other files written by the game
+pub fn user_data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
@@ -1,7 +1,65 @@
 #![allow(dead_code)]
-use crate::config::SCALE;
+use crate::config::{SCALE, SCREEN_W};
+use crate::game::physics::PhysicsProfile;
+use crate::replay;
+use crate::Result;
 use macroquad::experimental::collections::storage;
 use macroquad::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+// Upper bound for live records, so the buffer stays small while nothing draws it
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Name, step size and accessor of a tunable physics parameter
+type Tunable = (&'static str, f32, fn(&mut PhysicsProfile) -> &mut f32);
+
+/// Physics parameters editable in the tuning panel
+const TUNABLES: [Tunable; 10] = [
+    ("gravity y", 0.1, |p| &mut p.gravity.y),
+    ("gravity z", 0.01, |p| &mut p.gravity.z),
+    ("wall damping", 0.05, |p| &mut p.wall_damping),
+    ("actuator vel", 0.1, |p| &mut p.actuator_vel),
+    ("actuator stiffness", 0.05, |p| &mut p.actuator_stiffness),
+    ("actuator damping", 0.5, |p| &mut p.actuator_damping),
+    ("ball restitution", 0.05, |p| &mut p.ball_restitution),
+    ("ball friction", 0.05, |p| &mut p.ball_friction),
+    ("rolling resistance", 0.05, |p| &mut p.rolling_resistance),
+    ("rod friction", 0.05, |p| &mut p.rod_friction),
+];
+
+/// Developer panel for editing the physics profile of a running game
+pub struct TuningPanel {
+    pub visible: bool,
+    selected: usize,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Balls,
+    Rod,
+    Holes,
+    Obstacles,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Balls,
+        Category::Rod,
+        Category::Holes,
+        Category::Obstacles,
+    ];
+}
+
 pub enum DebugData {
     DebugGlyph(DebugGlyph),
     DebugText { key: String, value: String },
@@ -14,6 +72,8 @@ pub struct DebugGlyph {
     args_f32: Vec<f32>,
 }
 
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 enum GlyphKind {
     Circle,
     Line,
@@ -98,10 +158,124 @@ impl DebugData {
     }
 }
 
+/// A `DebugData` pushed during simulation step `frame`
+pub struct DebugRecord {
+    pub frame: u64,
+    pub category: Category,
+    /// Number of steps the record is kept for
+    pub lifetime: u64,
+    pub data: DebugData,
+}
+
+impl DebugRecord {
+    fn to_json(&self) -> serde_json::Value {
+        match &self.data {
+            DebugData::DebugGlyph(glyph) => json!({
+                "frame": self.frame,
+                "category": self.category,
+                "kind": glyph.kind,
+                "points": glyph.args_vec,
+                "values": glyph.args_f32,
+                "color": [glyph.color.r, glyph.color.g, glyph.color.b, glyph.color.a],
+            }),
+            DebugData::DebugText { key, value } => json!({
+                "frame": self.frame,
+                "category": self.category,
+                "kind": "text",
+                "key": key,
+                "value": value,
+            }),
+        }
+    }
+}
+
+/// Debug output of the simulation, filtered by category and kept in a
+/// bounded buffer, optionally traced to a JSON-lines file
+pub struct DebugChannel {
+    records: VecDeque<DebugRecord>,
+    frame: u64,
+    disabled: Vec<Category>,
+    trace: Option<BufWriter<File>>,
+}
+
+impl DebugChannel {
+    pub fn new() -> Self {
+        DebugChannel {
+            records: VecDeque::new(),
+            frame: 0,
+            disabled: vec![],
+            trace: None,
+        }
+    }
+
+    /// Also write every record to `file_name`, one JSON object per line
+    pub fn with_trace(file_name: &str) -> Result<Self> {
+        Ok(DebugChannel {
+            trace: Some(BufWriter::new(File::create(file_name)?)),
+            ..DebugChannel::new()
+        })
+    }
+
+    pub fn is_enabled(&self, category: Category) -> bool {
+        !self.disabled.contains(&category)
+    }
+
+    pub fn set_enabled(&mut self, category: Category, enabled: bool) {
+        self.disabled.retain(|c| *c != category);
+        if !enabled {
+            self.disabled.push(category);
+        }
+    }
+
+    /// Push a record kept until the next step
+    pub fn push(&mut self, category: Category, data: DebugData) {
+        self.push_for(category, 1, data);
+    }
+
+    pub fn push_for(&mut self, category: Category, lifetime: u64, data: DebugData) {
+        if !self.is_enabled(category) {
+            return;
+        }
+        let record = DebugRecord {
+            frame: self.frame,
+            category,
+            lifetime,
+            data,
+        };
+        if let Some(trace) = &mut self.trace {
+            if let Err(err) = writeln!(trace, "{}", record.to_json()) {
+                eprintln!("Debug trace disabled: {}", err);
+                self.trace = None;
+            }
+        }
+        if self.records.len() >= CHANNEL_CAPACITY {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Start a new simulation step, dropping expired records
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.records
+            .retain(|record| record.frame + record.lifetime > frame);
+        // Storage is never dropped, so flush instead of relying on the writer's drop
+        if let Some(trace) = &mut self.trace {
+            trace.flush().ok();
+        }
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &DebugRecord> {
+        self.records.iter()
+    }
+}
+
 pub fn draw_debug() {
-    let mut debug = storage::get_mut::<Vec<DebugData>>();
+    let debug = storage::get::<DebugChannel>();
     let mut y = 0;
-    for item in debug.iter() {
+    for record in debug.records() {
+        let item = &record.data;
         match item {
             DebugData::DebugGlyph(_) => {
                 item.draw_xy();
@@ -118,7 +292,98 @@ pub fn draw_debug() {
                 y += 1;
             }
         }
-        item.draw_xy();
     }
-    debug.clear();
+}
+
+impl TuningPanel {
+    pub fn new() -> Self {
+        TuningPanel {
+            visible: false,
+            selected: 0,
+            message: None,
+        }
+    }
+}
+
+/// F3 toggles the panel; while visible, page up/down select a parameter,
+/// minus/equals change it, 1-4 toggle debug categories and F5 exports the profile.
+/// `overrides` are the physics overrides of the current level.
+pub fn update_tuning(physics: &mut PhysicsProfile, overrides: &Map<String, Value>) {
+    let mut panel = storage::get_mut::<TuningPanel>();
+    if is_key_pressed(TOGGLE_KEY) {
+        panel.visible = !panel.visible;
+    }
+    if !panel.visible {
+        return;
+    }
+    if is_key_pressed(KeyCode::PageUp) {
+        panel.selected = (panel.selected + TUNABLES.len() - 1) % TUNABLES.len();
+    } else if is_key_pressed(KeyCode::PageDown) {
+        panel.selected = (panel.selected + 1) % TUNABLES.len();
+    }
+    let (_, step, value) = TUNABLES[panel.selected];
+    let change = is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::Equal);
+    if change && replay::is_recording() {
+        // Replays only store the profile the recording started with
+        panel.message = Some("no tuning while recording".to_string());
+    } else if is_key_pressed(KeyCode::Minus) {
+        *value(physics) -= step;
+    } else if is_key_pressed(KeyCode::Equal) {
+        *value(physics) += step;
+    }
+    let category_keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+    for (key, category) in category_keys.iter().zip(Category::ALL) {
+        if is_key_pressed(*key) {
+            let mut debug = storage::get_mut::<DebugChannel>();
+            let enabled = debug.is_enabled(category);
+            debug.set_enabled(category, !enabled);
+        }
+    }
+    if is_key_pressed(KeyCode::F5) {
+        panel.message = Some(match export_profile(physics, overrides) {
+            Ok(path) => format!("saved {}", path.display()),
+            Err(err) => err.to_string(),
+        });
+    }
+}
+
+/// Save the base profile with the edits made in the panel, leaving out the
+/// overrides of the level
+fn export_profile(physics: &PhysicsProfile, overrides: &Map<String, Value>) -> Result<PathBuf> {
+    let mut base = PhysicsProfile::load(&Map::new())?;
+    let mut level = PhysicsProfile::load(overrides)?;
+    let mut edited = physics.clone();
+    for (_, _, value) in TUNABLES.iter() {
+        *value(&mut base) += *value(&mut edited) - *value(&mut level);
+    }
+    base.save()
+}
+
+pub fn draw_tuning(physics: &PhysicsProfile) {
+    let panel = storage::get::<TuningPanel>();
+    if !panel.visible {
+        return;
+    }
+    draw_debug();
+    let mut physics = physics.clone();
+    let x = SCREEN_W - 620.;
+    draw_rectangle(x, 0., 620., 100. + TUNABLES.len() as f32 * 40., BLACK);
+    for (i, (name, _, value)) in TUNABLES.iter().enumerate() {
+        draw_text(
+            &format!("{}: {:.3}", name, value(&mut physics)),
+            x + 20.,
+            40. + i as f32 * 40.,
+            36.,
+            if i == panel.selected { YELLOW } else { WHITE },
+        );
+    }
+    let help = "pgup/pgdn select, -/= change, f5 export";
+    let status = panel.message.as_deref().unwrap_or(help);
+    draw_text(
+        status,
+        x + 20.,
+        40. + TUNABLES.len() as f32 * 40.,
+        30.,
+        GRAY,
+    );
 }
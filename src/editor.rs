@@ -1,53 +1,466 @@
 use crate::{
-    config::SCALE,
-    game::{level, rod},
-    state::{EditorState, Event, GameLevelState, GameState, Hole},
+    config::{SCALE, SCREEN_H, SCREEN_W},
+    game::{level, obstacles, rod, shapes::HoleShape},
+    history::EditorCommand,
+    level_format,
+    resources::{AssetSource, Resources},
+    state::{
+        EditorState, EditorTool, Event, GameLevelState, GameState, Hole, Obstacle, ObstacleKind,
+        BUMPER_KICK,
+    },
     Result,
 };
-use macroquad::prelude::*;
-use std::fs::File;
+use macroquad::{experimental::collections::storage, prelude::*};
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+};
+
+const MIN_RADIUS: f32 = 0.016;
+const MAX_RADIUS: f32 = 0.2;
+const RADIUS_STEP: f32 = 0.004;
+const GRID_SIZE: f32 = 0.02;
+// Shorter drags while placing a wall are ignored
+const MIN_WALL_LENGTH: f32 = 0.02;
+const ROTATION_STEP: f32 = std::f32::consts::PI / 12.;
 
 pub fn update_editor(game: &mut GameState, editor: &mut EditorState) -> Option<Event> {
-    editor.radius = ((editor.radius + mouse_wheel().1 * 6.).clamp(8., 100.) / 2.).round() * 2.;
-    if is_mouse_button_pressed(MouseButton::Left) {
-        game.level.holes.push(Hole {
-            pos: Vec2::from(mouse_position()) / SCALE * 2.,
-            radius: editor.radius / 500.,
-        })
+    let mouse = snap(editor, mouse_world());
+    let modifier = is_key_down(KeyCode::LeftSuper)
+        || is_key_down(KeyCode::RightSuper)
+        || is_key_down(KeyCode::LeftControl)
+        || is_key_down(KeyCode::RightControl);
+    let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+    if modifier && (is_key_pressed(KeyCode::Y) || (shift && is_key_pressed(KeyCode::Z))) {
+        editor.history.redo(&mut game.level);
+        editor.selected = None;
+        return None;
+    } else if modifier && is_key_pressed(KeyCode::Z) {
+        editor.history.undo(&mut game.level);
+        editor.selected = None;
+        return None;
+    }
+
+    if is_key_pressed(KeyCode::Tab) {
+        editor.tool = match editor.tool {
+            EditorTool::Holes => EditorTool::Goals,
+            EditorTool::Goals => EditorTool::Obstacles,
+            EditorTool::Obstacles => EditorTool::Holes,
+        };
+        editor.selected = None;
+        editor.drag_from = None;
+    } else if is_key_pressed(KeyCode::G) {
+        editor.grid = !editor.grid;
+    } else if is_key_pressed(KeyCode::B) {
+        let from = game.level.background_image.to_string();
+        let to = next_background(&from);
+        editor
+            .history
+            .execute(EditorCommand::SetBackground { from, to }, &mut game.level);
+    } else if is_key_pressed(KeyCode::S) {
+        editor.message = save_checked(&game.level).err();
+    } else if is_key_pressed(KeyCode::P) {
+        // Play-test the level as it is, without saving
+        editor.message = check(&game.level).err();
+        if editor.message.is_none() {
+            return Some(Event::PlayTestStarted);
+        }
     } else if is_key_pressed(KeyCode::Enter) {
-        save(&game.level, "level_new.json").expect("Failed to save");
+        editor.message = save_checked(&game.level).err();
+        if editor.message.is_none() {
+            return Some(Event::PlayTestStarted);
+        }
+    } else if is_key_pressed(KeyCode::Escape) && editor.selected.is_none() {
         return Some(Event::EditorClosed);
-    } else if is_key_down(KeyCode::LeftSuper) && is_key_pressed(KeyCode::Z) {
-        game.level.holes.pop();
     }
-
+    match editor.tool {
+        EditorTool::Holes => update_holes(&mut game.level, editor, mouse),
+        EditorTool::Goals => update_goals(&mut game.level, editor),
+        EditorTool::Obstacles => update_obstacles(&mut game.level, editor, mouse),
+    }
     None
 }
 
+fn update_holes(level: &mut GameLevelState, editor: &mut EditorState, mouse: Vec2) {
+    let wheel = match mouse_wheel().1 {
+        y if y > 0. => RADIUS_STEP,
+        y if y < 0. => -RADIUS_STEP,
+        _ => 0.,
+    };
+    match editor.selected {
+        Some(index) if wheel != 0. => {
+            let from = level.holes[index].radius;
+            let to = resize(from, wheel);
+            if to != from {
+                let resize = EditorCommand::ResizeHole { index, from, to };
+                editor.history.execute(resize, level);
+            }
+        }
+        None => editor.radius = resize(editor.radius, wheel),
+        _ => {}
+    }
+
+    let change: Option<fn(&HoleShape) -> HoleShape> = if is_key_pressed(KeyCode::H) {
+        Some(next_shape)
+    } else if is_key_pressed(KeyCode::R) {
+        Some(|shape| shape.rotated(ROTATION_STEP))
+    } else {
+        None
+    };
+    if let Some(change) = change {
+        // Change the selected hole, or the shape of new holes
+        match editor.selected {
+            Some(index) => {
+                let from = level.holes[index].shape.clone();
+                let to = change(&from);
+                let command = EditorCommand::SetHoleShape { index, from, to };
+                editor.history.execute(command, level);
+            }
+            None => editor.shape = change(&editor.shape),
+        }
+    }
+
+    if is_mouse_button_pressed(MouseButton::Left) {
+        editor.selected = hole_at(level, mouse_world());
+        if editor.selected.is_none() {
+            let hole = Hole {
+                pos: mouse,
+                radius: editor.radius,
+                shape: editor.shape.clone(),
+                ..Default::default()
+            };
+            editor.history.execute(EditorCommand::AddHole(hole), level);
+            editor.selected = Some(level.holes.len() - 1);
+        }
+        editor.drag_from = editor.selected.map(|i| level.holes[i].pos);
+        // Keep the point of the hole that was grabbed under the mouse
+        editor.drag_offset = editor
+            .drag_from
+            .map_or(Vec2::new(0., 0.), |pos| pos - mouse_world());
+    } else if is_mouse_button_down(MouseButton::Left) {
+        if let (Some(index), Some(_)) = (editor.selected, editor.drag_from) {
+            level.holes[index].pos = snap(editor, mouse_world() + editor.drag_offset);
+        }
+    } else if let (Some(index), Some(from)) = (editor.selected, editor.drag_from.take()) {
+        // The hole follows the mouse while dragging, record the move on release
+        let to = level.holes[index].pos;
+        if to != from {
+            editor
+                .history
+                .push(EditorCommand::MoveHole { index, from, to });
+        }
+    }
+
+    if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+        if let Some(index) = editor.selected.take() {
+            let remove = EditorCommand::RemoveHole {
+                index,
+                hole: level.holes[index].clone(),
+                goals: level.goals.clone(),
+            };
+            editor.history.execute(remove, level);
+        }
+    } else if is_key_pressed(KeyCode::Escape) {
+        editor.selected = None;
+    }
+}
+
+// Goals are assigned by clicking holes in the order they should be played
+fn update_goals(level: &mut GameLevelState, editor: &mut EditorState) {
+    let from = level.goals.clone();
+    let mut to = from.clone();
+    if is_mouse_button_pressed(MouseButton::Left) {
+        to.extend(hole_at(level, mouse_world()));
+    } else if is_mouse_button_pressed(MouseButton::Right) {
+        to.pop();
+    } else if is_key_pressed(KeyCode::C) {
+        to.clear();
+    }
+    if to != from {
+        editor
+            .history
+            .execute(EditorCommand::SetGoals { from, to }, level);
+    }
+}
+
+fn update_obstacles(level: &mut GameLevelState, editor: &mut EditorState, mouse: Vec2) {
+    if is_key_pressed(KeyCode::K) {
+        editor.obstacle = match editor.obstacle {
+            ObstacleKind::Post => ObstacleKind::Bumper,
+            ObstacleKind::Bumper => ObstacleKind::Wall,
+            ObstacleKind::Wall => ObstacleKind::Post,
+        };
+        editor.selected = None;
+    }
+    if editor.selected.is_none() {
+        match mouse_wheel().1 {
+            y if y > 0. => editor.radius = resize(editor.radius, RADIUS_STEP),
+            y if y < 0. => editor.radius = resize(editor.radius, -RADIUS_STEP),
+            _ => {}
+        }
+    }
+
+    if is_mouse_button_pressed(MouseButton::Left) {
+        editor.selected = obstacle_at(level, mouse_world());
+        if editor.selected.is_none() && editor.obstacle != ObstacleKind::Wall {
+            let obstacle = new_obstacle(editor, mouse, mouse);
+            editor
+                .history
+                .execute(EditorCommand::AddObstacle(obstacle), level);
+            editor.selected = Some(level.obstacles.len() - 1);
+        }
+        editor.drag_from = Some(mouse);
+        editor.drag_obstacle = editor.selected.map(|i| level.obstacles[i].clone());
+    } else if is_mouse_button_down(MouseButton::Left) {
+        if let (Some(index), Some(from), Some(obstacle)) =
+            (editor.selected, editor.drag_from, &editor.drag_obstacle)
+        {
+            level.obstacles[index] = obstacle.translated(mouse - from);
+        }
+    } else if let Some(from) = editor.drag_from.take() {
+        // Record the move on release, or add the wall drawn by dragging
+        match (editor.selected, editor.drag_obstacle.take()) {
+            (Some(index), Some(obstacle)) => {
+                let to = level.obstacles[index].clone();
+                if to != obstacle {
+                    editor.history.push(EditorCommand::ReplaceObstacle {
+                        index,
+                        from: obstacle,
+                        to,
+                    });
+                }
+            }
+            _ if from.distance(mouse) >= MIN_WALL_LENGTH => {
+                let wall = new_obstacle(editor, from, mouse);
+                editor
+                    .history
+                    .execute(EditorCommand::AddObstacle(wall), level);
+                editor.selected = Some(level.obstacles.len() - 1);
+            }
+            _ => {}
+        }
+    }
+
+    if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::Backspace) {
+        if let Some(index) = editor.selected.take() {
+            let remove = EditorCommand::RemoveObstacle {
+                index,
+                obstacle: level.obstacles[index].clone(),
+            };
+            editor.history.execute(remove, level);
+        }
+    } else if is_key_pressed(KeyCode::Escape) {
+        editor.selected = None;
+    }
+}
+
 pub fn draw_editor(game: &GameState, editor: &EditorState) {
     level::draw_background(game);
-    level::draw_holes(game);
-    rod::draw_rod(&game.objects.actuators, 0.);
+    if editor.grid {
+        draw_grid();
+    }
+    level::draw_holes(game, &game.level.holes);
+    obstacles::draw_obstacles(game);
+    rod::draw_rod(&game.objects.actuators, 0., &game.physics);
+    draw_goal_numbers(&game.level);
+
+    let mouse = snap(editor, mouse_world());
+    match editor.tool {
+        EditorTool::Holes => match editor.selected.and_then(|i| game.level.holes.get(i)) {
+            Some(hole) => level::draw_hole_lines(hole, 6., YELLOW),
+            None => level::draw_hole(
+                &Hole {
+                    pos: mouse,
+                    radius: editor.radius,
+                    shape: editor.shape.clone(),
+                    ..Default::default()
+                },
+                YELLOW,
+            ),
+        },
+        EditorTool::Goals => draw_circle_lines(mouse.x * SCALE, mouse.y * SCALE, 12., 4., YELLOW),
+        EditorTool::Obstacles => match editor.selected.and_then(|i| game.level.obstacles.get(i)) {
+            Some(obstacle) => {
+                for point in obstacle.points() {
+                    draw_circle_lines(point.x * SCALE, point.y * SCALE, 16., 4., YELLOW);
+                }
+            }
+            None => {
+                let from = editor.drag_from.unwrap_or(mouse);
+                obstacles::draw_obstacle(&new_obstacle(editor, from, mouse), 0.5);
+            }
+        },
+    }
+
     draw_rectangle(0., 0., screen_width() * 2., 120., BLACK);
-    let mouse = Vec2::from(mouse_position()) / SCALE * 2.;
+    let (tool, help) = match editor.tool {
+        EditorTool::Holes => (
+            "holes",
+            "click add/select, drag move, wheel resize, h shape, r rotate, del remove",
+        ),
+        EditorTool::Goals => ("goals", "click add goal, right click remove, c clear"),
+        EditorTool::Obstacles => (
+            match editor.obstacle {
+                ObstacleKind::Post => "posts",
+                ObstacleKind::Bumper => "bumpers",
+                ObstacleKind::Wall => "walls",
+            },
+            "click add/select, drag move or draw wall, k kind, del remove",
+        ),
+    };
     draw_text(
-        &format!("Editor - radius {}", editor.radius),
+        &format!(
+            "Editor - {} - radius {:.3} - grid {}",
+            tool,
+            editor.radius,
+            if editor.grid { "on" } else { "off" }
+        ),
         30.0,
         30.0,
         48.0,
         YELLOW,
     );
-
-    draw_circle(
-        mouse.x * SCALE,
-        mouse.y * SCALE,
-        editor.radius / 500. * SCALE,
-        YELLOW,
+    let status = editor.message.as_deref().unwrap_or(help);
+    draw_text(status, 30.0, 70.0, 36.0, WHITE);
+    draw_text(
+        "tab tool, g grid, b background, ctrl+z undo, ctrl+y redo, s save, p test, esc exit",
+        30.0,
+        105.0,
+        36.0,
+        GRAY,
     );
 }
 
+fn draw_grid() {
+    let color = Color::new(1., 1., 1., 0.15);
+    let step = GRID_SIZE * 5.;
+    for i in 0..=(SCREEN_W / SCALE / step) as i32 {
+        let x = i as f32 * step * SCALE;
+        draw_line(x, 0., x, SCREEN_H, 2., color);
+    }
+    for i in 0..=(SCREEN_H / SCALE / step) as i32 {
+        let y = i as f32 * step * SCALE;
+        draw_line(0., y, SCREEN_W, y, 2., color);
+    }
+}
+
+fn draw_goal_numbers(level: &GameLevelState) {
+    for (n, i) in level.goals.iter().enumerate() {
+        if let Some(hole) = level.holes.get(*i) {
+            draw_text(
+                &(n + 1).to_string(),
+                hole.pos.x * SCALE - 12. + n as f32 * 4.,
+                hole.pos.y * SCALE + 12.,
+                48.,
+                WHITE,
+            );
+        }
+    }
+}
+
+/// Obstacle of the editor's kind placed at `pos`, walls run from `from` to `pos`
+fn new_obstacle(editor: &EditorState, from: Vec2, pos: Vec2) -> Obstacle {
+    match editor.obstacle {
+        ObstacleKind::Post => Obstacle::Post {
+            pos,
+            radius: editor.radius / 2.,
+        },
+        ObstacleKind::Bumper => Obstacle::Bumper {
+            pos,
+            radius: editor.radius / 2.,
+            kick: BUMPER_KICK,
+        },
+        ObstacleKind::Wall => Obstacle::Wall { from, to: pos },
+    }
+}
+
+/// Background after `current` in name order, wrapping around
+fn next_background(current: &str) -> String {
+    let resources = storage::get::<Resources>();
+    let mut names: Vec<&String> = resources.backgrounds.keys().collect();
+    names.sort();
+    let next = names
+        .iter()
+        .position(|name| *name == current)
+        .map_or(0, |i| (i + 1) % names.len());
+    names
+        .get(next)
+        .map_or(current.to_string(), |name| name.to_string())
+}
+
+fn mouse_world() -> Vec2 {
+    Vec2::from(mouse_position()) / SCALE * 2.
+}
+
+fn snap(editor: &EditorState, pos: Vec2) -> Vec2 {
+    match editor.grid {
+        true => (pos / GRID_SIZE).round() * GRID_SIZE,
+        false => pos,
+    }
+}
+
+fn resize(radius: f32, delta: f32) -> f32 {
+    ((radius + delta).clamp(MIN_RADIUS, MAX_RADIUS) / RADIUS_STEP).round() * RADIUS_STEP
+}
+
+fn obstacle_at(level: &GameLevelState, pos: Vec2) -> Option<usize> {
+    level
+        .obstacles
+        .iter()
+        .rposition(|obstacle| obstacle.contains(pos))
+}
+
+fn hole_at(level: &GameLevelState, pos: Vec2) -> Option<usize> {
+    level.holes.iter().rposition(|hole| hole.contains(pos))
+}
+
+fn next_shape(shape: &HoleShape) -> HoleShape {
+    match shape {
+        HoleShape::Circle => HoleShape::Ellipse {
+            aspect: 0.6,
+            angle: 0.,
+        },
+        HoleShape::Ellipse { .. } => HoleShape::Capsule {
+            length: 2.,
+            angle: 0.,
+        },
+        HoleShape::Capsule { .. } => HoleShape::Polygon {
+            // Regular pentagon
+            points: (0..5)
+                .map(|i| {
+                    let angle =
+                        i as f32 * 2. * std::f32::consts::PI / 5. - std::f32::consts::PI / 2.;
+                    Vec2::new(angle.cos(), angle.sin())
+                })
+                .collect(),
+        },
+        HoleShape::Polygon { .. } => HoleShape::Circle,
+    }
+}
+
+/// Validate, returning the first problem found as a message
+fn check(level: &GameLevelState) -> std::result::Result<(), String> {
+    match level_format::validate(level).first() {
+        Some(error) => Err(error.to_string()),
+        None => Ok(()),
+    }
+}
+
+fn save_checked(level: &GameLevelState) -> std::result::Result<(), String> {
+    check(level)?;
+    save(level, &level.level_file).map_err(|err| err.to_string())
+}
+
+// Save to the user levels directory, where the game can load it back
 fn save(level: &GameLevelState, file_name: &str) -> Result<()> {
-    let file = File::create(file_name)?;
+    let path = match AssetSource::user_levels_dir() {
+        Some(dir) => {
+            fs::create_dir_all(&dir)?;
+            dir.join(file_name)
+        }
+        None => PathBuf::from(file_name),
+    };
+    let file = File::create(path)?;
     serde_json::to_writer(&file, level)?;
     Ok(())
 }
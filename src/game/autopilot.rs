@@ -0,0 +1,323 @@
+use crate::{
+    config::{SCALE, SCREEN_H, SCREEN_W},
+    game::rod,
+    input::{Input, InputSource},
+    state::{Ball, GameState, Hole},
+};
+use macroquad::math::Vec2;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+// Size of the cells of the planning grid, m
+const CELL: f32 = 0.01;
+// Distance kept between the ball's center and the rim of holes to avoid, m
+const CLEARANCE: f32 = 0.025;
+// How far along the planned path the ball is steered to, cells
+const LOOKAHEAD: usize = 6;
+// Cost of moving to a neighbouring cell, straight and diagonally
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+// Extra cost of entering a cell the ball should not be in, so it only
+// crosses one when there is no way around
+const BLOCKED_COST: u32 = 10_000;
+// Largest ball speed along the rod when steering, m/s
+const MAX_BALL_VEL: f32 = 0.4;
+// Largest rod tilt when steering, rad
+const MAX_TILT: f32 = 0.15;
+// Lift per meter the ball is below the point it is steered to
+const LIFT_GAIN: f32 = 20.;
+
+/// Controller that steers the ball into the goal hole along a planned path
+/// around the other holes and the obstacles. Drives the game without a
+/// window to check that a level can be cleared.
+pub struct Autopilot {
+    plan: Option<Plan>,
+}
+
+/// Cost of the cheapest path to the goal from each cell of the grid
+struct Plan {
+    goal_index: usize,
+    snapshot: Vec<[i32; 3]>,
+    grid: Grid,
+    cost: Vec<u32>,
+}
+
+/// Square cells of `CELL` m over the playfield, numbered row by row
+#[derive(Debug, Clone, Copy)]
+struct Grid {
+    cols: usize,
+    rows: usize,
+}
+
+/// Axis aligned bounds of a hole, grown by `margin`
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min: Vec2,
+    max: Vec2,
+}
+
+impl Bounds {
+    fn of(hole: &Hole, margin: f32) -> Self {
+        let outline = hole.outline();
+        let min = outline.iter().fold(hole.pos, |min, point| min.min(*point));
+        let max = outline.iter().fold(hole.pos, |max, point| max.max(*point));
+        let margin = Vec2::new(margin, margin);
+        Bounds {
+            min: min - margin,
+            max: max + margin,
+        }
+    }
+}
+
+impl Autopilot {
+    pub fn new() -> Self {
+        Autopilot { plan: None }
+    }
+}
+
+impl InputSource for Autopilot {
+    fn next_input(&mut self, game: &GameState) -> Input {
+        let ball = match game.objects.balls.iter().find(|ball| ball.active) {
+            // Nothing to steer while the ball drops into a hole
+            Some(ball) if ball.pos.z >= 0. => ball,
+            _ => return Input::default(),
+        };
+        let goal_index = game.get_goal_hole();
+        let goal = match game.objects.holes.get(goal_index) {
+            Some(goal) => goal,
+            None => return Input::default(),
+        };
+        // Plan again only when the goal changes or a hole moved to another cell
+        let holes = largest_holes(game);
+        let snapshot = snapshot(&holes);
+        let plan = match self.plan.take() {
+            Some(plan) if plan.goal_index == goal_index && plan.snapshot == snapshot => plan,
+            _ => Plan::new(game, goal_index, &holes, snapshot),
+        };
+        // Over the open goal, the ball only drops once clear of the rim
+        let target = match goal.contains(ball.pos.truncate()) {
+            true => goal.pos,
+            false => plan.waypoint(ball.pos.truncate()).unwrap_or(goal.pos),
+        };
+        self.plan = Some(plan);
+        steer(game, ball, target)
+    }
+}
+
+impl Plan {
+    fn new(game: &GameState, goal_index: usize, holes: &[Hole], snapshot: Vec<[i32; 3]>) -> Self {
+        let grid = Grid {
+            cols: (SCREEN_W / SCALE / CELL).ceil() as usize,
+            rows: (SCREEN_H / SCALE / CELL).ceil() as usize,
+        };
+        let hazards = holes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != goal_index)
+            .map(|(_, hole)| hole);
+        let blocked = blocked_cells(game, grid, hazards);
+
+        // Dijkstra from the cells inside the goal, where it is now
+        let mut cost = vec![u32::MAX; grid.cols * grid.rows];
+        let goal = &holes[goal_index];
+        let mut queue = BinaryHeap::new();
+        for cell in grid.cells_around(Bounds::of(goal, 0.)) {
+            if goal.contains(grid.center(cell)) {
+                cost[cell] = 0;
+                queue.push(Reverse((0, cell)));
+            }
+        }
+        while let Some(Reverse((cell_cost, cell))) = queue.pop() {
+            if cell_cost > cost[cell] {
+                continue;
+            }
+            for (next, step) in grid.neighbours(cell) {
+                let next_cost = cell_cost + step + blocked[next] as u32 * BLOCKED_COST;
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    queue.push(Reverse((next_cost, next)));
+                }
+            }
+        }
+        Plan {
+            goal_index,
+            snapshot,
+            grid,
+            cost,
+        }
+    }
+
+    /// Point `LOOKAHEAD` cells along the cheapest path from `pos`, or none
+    /// when `pos` is off the grid or the goal can not be reached
+    fn waypoint(&self, pos: Vec2) -> Option<Vec2> {
+        let mut cell = self.grid.cell_at(pos)?;
+        if self.cost[cell] == u32::MAX {
+            return None;
+        }
+        for _ in 0..LOOKAHEAD {
+            let next = self
+                .grid
+                .neighbours(cell)
+                .map(|(next, _)| next)
+                .min_by_key(|next| self.cost[*next])?;
+            if self.cost[next] >= self.cost[cell] {
+                break;
+            }
+            cell = next;
+        }
+        Some(self.grid.center(cell))
+    }
+}
+
+impl Grid {
+    fn cell_at(&self, pos: Vec2) -> Option<usize> {
+        let col = (pos.x / CELL).floor();
+        let row = (pos.y / CELL).floor();
+        match col >= 0. && row >= 0. && (col as usize) < self.cols && (row as usize) < self.rows {
+            true => Some(row as usize * self.cols + col as usize),
+            false => None,
+        }
+    }
+
+    fn center(&self, cell: usize) -> Vec2 {
+        let (row, col) = (cell / self.cols, cell % self.cols);
+        Vec2::new(col as f32 + 0.5, row as f32 + 0.5) * CELL
+    }
+
+    fn cells_around(self, bounds: Bounds) -> impl Iterator<Item = usize> {
+        let first = |v: f32| (v / CELL).floor().max(0.) as usize;
+        let (min_col, min_row) = (first(bounds.min.x), first(bounds.min.y));
+        let max_col = first(bounds.max.x).min(self.cols - 1);
+        let max_row = first(bounds.max.y).min(self.rows - 1);
+        (min_row..=max_row)
+            .flat_map(move |row| (min_col..=max_col).map(move |col| row * self.cols + col))
+    }
+
+    /// The up to eight cells around `cell`, with the cost of moving there
+    fn neighbours(self, cell: usize) -> impl Iterator<Item = (usize, u32)> {
+        let (row, col) = ((cell / self.cols) as i32, (cell % self.cols) as i32);
+        (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy)| (*dx, *dy) != (0, 0))
+            .filter_map(move |(dx, dy)| {
+                let (r, c) = (row + dy, col + dx);
+                if r < 0 || c < 0 || r as usize >= self.rows || c as usize >= self.cols {
+                    return None;
+                }
+                let step = match dx != 0 && dy != 0 {
+                    true => DIAGONAL_COST,
+                    false => STRAIGHT_COST,
+                };
+                Some((r as usize * self.cols + c as usize, step))
+            })
+    }
+}
+
+/// Cells the ball's center must keep out of: near holes and walls,
+/// or too close to an obstacle
+fn blocked_cells<'a>(
+    game: &GameState,
+    grid: Grid,
+    hazards: impl Iterator<Item = &'a Hole>,
+) -> Vec<bool> {
+    let mut blocked = vec![false; grid.cols * grid.rows];
+    for hole in hazards {
+        for cell in grid.cells_around(Bounds::of(hole, CLEARANCE)) {
+            let pos = grid.center(cell);
+            blocked[cell] |= hole.contains(pos) || hole.rim(pos).0.distance(pos) < CLEARANCE;
+        }
+    }
+    let radius = game.physics.ball_radius;
+    for (cell, blocked) in blocked.iter_mut().enumerate() {
+        let pos = grid.center(cell);
+        *blocked |= pos.x < radius || pos.x > SCREEN_W / SCALE - radius;
+        *blocked |= game.level.obstacles.iter().any(|obstacle| {
+            let (nearest, obstacle_radius) = obstacle.nearest(pos);
+            nearest.distance(pos) < obstacle_radius + radius + CLEARANCE
+        });
+    }
+    blocked
+}
+
+/// Every hole where it is now, at its largest. Closed holes count too, they
+/// may open before the ball gets past them.
+fn largest_holes(game: &GameState) -> Vec<Hole> {
+    game.objects
+        .holes
+        .iter()
+        .zip(game.level.holes.iter())
+        .map(|(current, hole)| Hole {
+            pos: current.pos,
+            radius: f32::max(current.radius, hole.radius),
+            ..hole.clone()
+        })
+        .collect()
+}
+
+/// Positions and sizes of `holes` in whole cells. Moving holes only change
+/// it once they get to another cell, not on every step.
+fn snapshot(holes: &[Hole]) -> Vec<[i32; 3]> {
+    holes
+        .iter()
+        .map(|hole| [hole.pos.x, hole.pos.y, hole.radius].map(|v| (v / CELL).round() as i32))
+        .collect()
+}
+
+/// Tilt the rod so the ball rolls towards `target` and slows down there,
+/// and raise or lower it to bring the ball to the height of `target`
+fn steer(game: &GameState, ball: &Ball, target: Vec2) -> Input {
+    let ball_vel = ((target.x - ball.pos.x) * 2.).clamp(-MAX_BALL_VEL, MAX_BALL_VEL);
+    let tilt = ((ball_vel - ball.vel.x) * 0.3).clamp(-MAX_TILT, MAX_TILT);
+    let turn = ((tilt - rod::rod_angle(&game.objects.actuators)) * 8.).clamp(-1., 1.);
+    // Keep enough travel for turning, steering matters more than lifting
+    let headroom = 1. - turn.abs();
+    let lift = ((ball.pos.y - target.y) * LIFT_GAIN).clamp(-headroom, headroom);
+    Input {
+        actuators: [lift + turn, lift - turn],
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::sim::Simulation, state::Event, timestep::FIXED_DELTATIME, PROVE_MAX_STEPS};
+
+    #[test]
+    fn clears_example_level() {
+        let mut sim = Simulation::new(GameState::load("level_example.json").unwrap());
+        let events = sim.run(&mut Autopilot::new(), FIXED_DELTATIME, PROVE_MAX_STEPS);
+        assert_eq!(events.last(), Some(&Event::GameCompleted), "{:?}", events);
+    }
+
+    fn planned(autopilot: &mut Autopilot, game: &GameState) -> *const u32 {
+        autopilot.next_input(game);
+        autopilot.plan.as_ref().unwrap().cost.as_ptr()
+    }
+
+    #[test]
+    fn plans_to_where_the_goal_is_now() {
+        let mut game = GameState::load("level_example.json").unwrap();
+        let goal_index = game.get_goal_hole();
+        let moved = game.objects.holes[goal_index].pos + Vec2::new(0.1, 0.05);
+        game.objects.holes[goal_index].pos = moved;
+
+        let mut autopilot = Autopilot::new();
+        autopilot.next_input(&game);
+        let plan = autopilot.plan.as_ref().unwrap();
+        assert_eq!(plan.cost[plan.grid.cell_at(moved).unwrap()], 0);
+    }
+
+    #[test]
+    fn plans_again_once_a_hole_moves_to_another_cell() {
+        let mut game = GameState::load("level_example.json").unwrap();
+        let hole = (game.get_goal_hole() + 1) % game.objects.holes.len();
+        let mut autopilot = Autopilot::new();
+        let first = planned(&mut autopilot, &game);
+
+        game.objects.holes[hole].pos.x += CELL * 0.1;
+        assert_eq!(planned(&mut autopilot, &game), first);
+
+        game.objects.holes[hole].pos.x += CELL;
+        assert_ne!(planned(&mut autopilot, &game), first);
+    }
+}
@@ -1,26 +1,104 @@
 use std::f32::consts::PI;
 
 use crate::config::SCALE;
-use crate::{game::game::DARKGRAY_SHADOW, resources::Resources, state::Ball};
+use crate::{
+    game::{game::DARKGRAY_SHADOW, physics::PhysicsProfile},
+    resources::Resources,
+    state::Ball,
+};
 use macroquad::{experimental::collections::storage, math::Vec3, prelude::*};
-pub const BALL_RADIUS: f32 = 0.03;
 
-pub fn update_balls(balls: &mut Vec<Ball>, dt: f32) {
+pub fn update_balls(balls: &mut [Ball], physics: &PhysicsProfile, dt: f32) {
     for ball in balls.iter_mut() {
         if !ball.active {
             continue;
         }
-        let forces: Vec3 = ball.forces.iter().sum();
+        let forces: Vec3 = physics.gravity + ball.forces.iter().sum::<Vec3>();
         let impulses: Vec3 = ball.impulses.iter().sum();
+        let angular_impulses: Vec3 = ball.angular_impulses.iter().sum();
         ball.impulses.clear();
+        ball.angular_impulses.clear();
 
         ball.vel += forces * dt + impulses;
         ball.pos += ball.vel * dt;
+
+        ball.spin += angular_impulses;
+        ball.spin *= f32::max(0., 1. - physics.rolling_resistance * dt);
+        // Only spin around the view axis is visible
+        ball.rotation += ball.spin.z * dt;
+    }
+}
+
+/// Resolve sphere-sphere contacts between balls
+pub fn update_ball_collisions(balls: &mut [Ball], physics: &PhysicsProfile) {
+    for j in 1..balls.len() {
+        let (left, right) = balls.split_at_mut(j);
+        let b = &mut right[0];
+        for a in left.iter_mut() {
+            if !a.active || !b.active {
+                continue;
+            }
+            let delta = b.pos - a.pos;
+            let distance = delta.length();
+            if distance >= 2. * physics.ball_radius || distance == 0. {
+                continue;
+            }
+            // Solve collision by moving both balls apart along the normal
+            let normal = delta / distance;
+            let intrusion = 2. * physics.ball_radius - distance;
+            a.pos -= normal * intrusion / 2.;
+            b.pos += normal * intrusion / 2.;
+
+            // Correct velocity unless the balls are already separating,
+            // spin moves the surfaces past each other at the contact point
+            let contact_a = normal * physics.ball_radius;
+            let contact_b = -contact_a;
+            let rel_vel = a.vel + a.spin.cross(contact_a) - b.vel - b.spin.cross(contact_b);
+            if rel_vel.dot(normal) <= 0. {
+                continue;
+            }
+            // Each ball resists sliding at its rim with 1/m + r^2/I = 7/(2m)
+            let mass = physics.ball_mass;
+            let impulse = contact_impulse(rel_vel, normal, 2. / mass, 7. / mass, physics);
+            a.impulses.push(-impulse / mass);
+            b.impulses.push(impulse / mass);
+            let inertia = physics.ball_inertia();
+            a.angular_impulses.push(contact_a.cross(-impulse) / inertia);
+            b.angular_impulses.push(contact_b.cross(impulse) / inertia);
+        }
     }
 }
 
-pub fn draw_balls(balls: &Vec<Ball>, rod_angle: f32) {
+/// Impulse (kg*m/s) between two bodies whose contact points approach with
+/// `rel_vel` along `normal`. `inv_mass` is the sum of their inverse masses and
+/// `tangent_inv_mass` the same across the normal, where turning the bodies
+/// adds to it. Friction is limited by the Coulomb cone around the normal impulse.
+pub fn contact_impulse(
+    rel_vel: Vec3,
+    normal: Vec3,
+    inv_mass: f32,
+    tangent_inv_mass: f32,
+    physics: &PhysicsProfile,
+) -> Vec3 {
+    let approach = rel_vel.dot(normal);
+    let normal_impulse = (1. + physics.ball_restitution) * approach / inv_mass;
+    let tangent_vel = rel_vel - normal * approach;
+    let tangent_speed = tangent_vel.length();
+    let tangent_impulse = if tangent_speed > f32::EPSILON {
+        let friction = f32::min(
+            tangent_speed / tangent_inv_mass,
+            physics.ball_friction * normal_impulse,
+        );
+        tangent_vel / tangent_speed * friction
+    } else {
+        Vec3::new(0.0, 0.0, 0.0)
+    };
+    normal * normal_impulse + tangent_impulse
+}
+
+pub fn draw_balls(balls: &[Ball], physics: &PhysicsProfile) {
     let resources = storage::get_mut::<Resources>();
+    let radius = physics.ball_radius;
     for ref ball in balls.iter() {
         // Wall shadow
         let ball_shadow_pos = ball.pos.truncate()
@@ -31,40 +109,40 @@ pub fn draw_balls(balls: &Vec<Ball>, rod_angle: f32) {
         draw_circle(
             ball_shadow_pos.x * SCALE,
             ball_shadow_pos.y * SCALE,
-            BALL_RADIUS * SCALE,
+            radius * SCALE,
             DARKGRAY_SHADOW,
         );
         // Ball
         draw_texture_ex(
             resources.ball_bg,
-            (ball.pos.x - BALL_RADIUS) * SCALE,
-            (ball.pos.y - BALL_RADIUS) * SCALE,
+            (ball.pos.x - radius) * SCALE,
+            (ball.pos.y - radius) * SCALE,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(Vec2::new(BALL_RADIUS, BALL_RADIUS) * 2. * SCALE),
+                dest_size: Some(Vec2::new(radius, radius) * 2. * SCALE),
                 ..Default::default()
             },
         );
         draw_texture_ex(
             resources.ball_fg,
-            (ball.pos.x - BALL_RADIUS) * SCALE,
-            (ball.pos.y - BALL_RADIUS) * SCALE,
+            (ball.pos.x - radius) * SCALE,
+            (ball.pos.y - radius) * SCALE,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(Vec2::new(BALL_RADIUS, BALL_RADIUS) * 2. * SCALE),
-                rotation: rod_angle,
+                dest_size: Some(Vec2::new(radius, radius) * 2. * SCALE),
+                rotation: ball.rotation,
                 ..Default::default()
             },
         );
         // Hole shadow
         if ball.in_hole.is_some() {
-            let r = (ball.pos.z).clamp(-BALL_RADIUS, 0.) / (-BALL_RADIUS);
-            let shadow_r = BALL_RADIUS * ((r * PI / 2.).sin());
+            let r = (ball.pos.z).clamp(-radius, 0.) / (-radius);
+            let shadow_r = radius * ((r * PI / 2.).sin());
             draw_poly_lines(
                 ball.pos.x * SCALE,
                 ball.pos.y * SCALE,
                 20,
-                (BALL_RADIUS - shadow_r / 2.) * SCALE + 1.,
+                (radius - shadow_r / 2.) * SCALE + 1.,
                 0.,
                 shadow_r * SCALE + 2.,
                 Color::from_rgba(40, 40, 40, 255),
@@ -73,7 +151,7 @@ pub fn draw_balls(balls: &Vec<Ball>, rod_angle: f32) {
                 ball.pos.x * SCALE,
                 ball.pos.y * SCALE,
                 20,
-                (BALL_RADIUS - shadow_r / 2.) * SCALE + 1.,
+                (radius - shadow_r / 2.) * SCALE + 1.,
                 360. / 20. / 2.,
                 shadow_r * SCALE + 2.,
                 Color::from_rgba(40, 40, 40, 255),
@@ -81,3 +159,102 @@ pub fn draw_balls(balls: &Vec<Ball>, rod_angle: f32) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(physics: &PhysicsProfile, pos: Vec3, vel: Vec3) -> Ball {
+        Ball {
+            pos,
+            vel,
+            ..Ball::new(physics)
+        }
+    }
+
+    // Collide two balls and apply the resulting impulses
+    fn collide(physics: &PhysicsProfile, balls: &mut [Ball; 2]) {
+        update_ball_collisions(balls, physics);
+        for ball in balls.iter_mut() {
+            ball.vel += ball.impulses.iter().sum::<Vec3>();
+            ball.spin += ball.angular_impulses.iter().sum::<Vec3>();
+        }
+    }
+
+    fn momentum(physics: &PhysicsProfile, balls: &[Ball; 2]) -> Vec3 {
+        (balls[0].vel + balls[1].vel) * physics.ball_mass
+    }
+
+    // Around the origin, including the balls' own spin
+    fn angular_momentum(physics: &PhysicsProfile, balls: &[Ball; 2]) -> Vec3 {
+        let of = |ball: &Ball| {
+            ball.pos.cross(ball.vel * physics.ball_mass) + ball.spin * physics.ball_inertia()
+        };
+        of(&balls[0]) + of(&balls[1])
+    }
+
+    fn approach(balls: &[Ball; 2]) -> f32 {
+        let normal = (balls[1].pos - balls[0].pos).normalize();
+        (balls[0].vel - balls[1].vel).dot(normal)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn head_on_collision_conserves_momentum() {
+        let physics = PhysicsProfile::default();
+        let gap = 1.9 * physics.ball_radius;
+        let mut balls = [
+            ball(&physics, Vec3::new(0.5, 1., 0.), Vec3::new(0.8, 0., 0.)),
+            ball(
+                &physics,
+                Vec3::new(0.5 + gap, 1., 0.),
+                Vec3::new(-0.2, 0., 0.),
+            ),
+        ];
+        let (before, approach_before) = (momentum(&physics, &balls), approach(&balls));
+        collide(&physics, &mut balls);
+        assert_close(momentum(&physics, &balls), before);
+        let separation = -approach(&balls);
+        assert!((separation - physics.ball_restitution * approach_before).abs() < 1e-5);
+        // Nothing slides across the normal, so nothing starts to spin
+        assert_close(balls[0].spin, Vec3::new(0., 0., 0.));
+        assert_close(balls[1].spin, Vec3::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn glancing_collision_conserves_momentum_and_spins_balls() {
+        let physics = PhysicsProfile::default();
+        let offset = Vec3::new(1.2, 1.4, 0.).normalize() * 1.95 * physics.ball_radius;
+        let mut balls = [
+            ball(&physics, Vec3::new(0.5, 1., 0.), Vec3::new(0., 1., 0.)),
+            ball(
+                &physics,
+                Vec3::new(0.5, 1., 0.) + offset,
+                Vec3::new(0., 0., 0.),
+            ),
+        ];
+        balls[1].spin = Vec3::new(0., 0., 20.);
+        let approach_before = approach(&balls);
+        let mut before = balls.clone();
+        collide(&physics, &mut balls);
+        assert_close(momentum(&physics, &balls), momentum(&physics, &before));
+        // Taken where the balls were pushed apart to, so only the impulses count
+        for (ball, moved) in before.iter_mut().zip(balls.iter()) {
+            ball.pos = moved.pos;
+        }
+        let (before, after) = (
+            angular_momentum(&physics, &before),
+            angular_momentum(&physics, &balls),
+        );
+        assert!(after.distance(before) < 1e-8, "{} != {}", after, before);
+        let separation = -approach(&balls);
+        assert!((separation - physics.ball_restitution * approach_before).abs() < 1e-5);
+        // Friction at the contact turns both balls the same way
+        let spun = balls[0].spin;
+        assert!(spun.z.abs() > 0.);
+        assert_close(balls[1].spin - Vec3::new(0., 0., 20.), spun);
+    }
+}
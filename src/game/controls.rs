@@ -0,0 +1,105 @@
+use super::menu::get_text_params;
+use crate::{
+    bindings::{Action, KeyBindings},
+    config::{SCREEN_H, SCREEN_W},
+    input::Input,
+    resources::Resources,
+    settings::Settings,
+    state::{ControlsState, Event},
+    utils::draw_centered_text,
+};
+use macroquad::{experimental::collections::storage, prelude::*};
+
+const FONT_SIZE_TITLE: f32 = 112.;
+const FONT_SIZE_ROW: f32 = 72.;
+// Rows after the actions
+const RESET_ROW: usize = Action::ALL.len();
+const BACK_ROW: usize = Action::ALL.len() + 1;
+
+pub fn update_controls(controls: &mut ControlsState, input: &Input) -> Option<Event> {
+    if controls.waiting {
+        // The escape key always cancels, so it can not lock the player out
+        if is_key_pressed(KeyCode::Escape) {
+            controls.waiting = false;
+        } else if let Some(key) = get_last_key_pressed() {
+            let action = Action::ALL[controls.selected];
+            controls.waiting = !controls.settings.bindings.bind(action, key);
+            controls.message = match controls.waiting {
+                true => Some(format!("{:?} can not be bound", key)),
+                false => None,
+            };
+        }
+        return None;
+    }
+
+    let rows = BACK_ROW + 1;
+    if input.menu_up {
+        controls.selected = (controls.selected + rows - 1) % rows;
+    } else if input.menu_down {
+        controls.selected = (controls.selected + 1) % rows;
+    }
+    if input.enter {
+        match controls.selected {
+            RESET_ROW => controls.settings.bindings = KeyBindings::default(),
+            BACK_ROW => return Some(close(controls)),
+            _ => controls.waiting = true,
+        }
+    } else if input.escape {
+        return Some(close(controls));
+    }
+    None
+}
+
+/// Save the settings and use them from now on
+fn close(controls: &ControlsState) -> Event {
+    if let Err(err) = controls.settings.save() {
+        eprintln!("Could not save settings: {}", err);
+    }
+    storage::store::<Settings>(controls.settings.clone());
+    Event::ControlsClosed
+}
+
+pub fn draw_controls(controls: &ControlsState) {
+    let resources = storage::get::<Resources>();
+    let y_start = SCREEN_H / 4.;
+    draw_centered_text(
+        "controls",
+        SCREEN_W / 2.,
+        y_start,
+        get_text_params(&resources, FONT_SIZE_TITLE, WHITE),
+    );
+    let bindings = &controls.settings.bindings;
+    let mut rows: Vec<String> = Action::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, action)| {
+            let key = match controls.waiting && i == controls.selected {
+                true => "?".to_string(),
+                false => format!("{:?}", bindings.key(*action)).to_lowercase(),
+            };
+            format!("{: <10} {: >9}", action.name(), key)
+        })
+        .collect();
+    rows.push("reset".to_string());
+    rows.push("back".to_string());
+    for (i, row) in rows.iter().enumerate() {
+        let color = match i == controls.selected {
+            true => Color::from_rgba(254, 93, 29, 255),
+            false => WHITE,
+        };
+        draw_centered_text(
+            row,
+            SCREEN_W / 2.,
+            y_start + FONT_SIZE_TITLE + (i + 1) as f32 * FONT_SIZE_ROW,
+            get_text_params(&resources, FONT_SIZE_ROW, color),
+        );
+    }
+    if let Some(message) = &controls.message {
+        draw_centered_text(
+            message,
+            SCREEN_W / 2.,
+            SCREEN_H - FONT_SIZE_ROW * 2.,
+            get_text_params(&resources, FONT_SIZE_ROW, GRAY),
+        );
+    }
+}
@@ -1,29 +1,30 @@
 use super::balls;
 use super::help;
 use super::level;
+use super::obstacles;
 use super::rod;
+use super::sim;
 use crate::{
     config::{SCREEN_H, SCREEN_W},
+    debug::DebugChannel,
     input::Input,
     state::{Event, GameState},
-    utils::return_if_some,
 };
-use macroquad::prelude::*;
+use macroquad::{experimental::collections::storage, prelude::*};
 
 pub const DARKGRAY_SHADOW: Color = Color::new(0., 0., 0., 0.5);
 
 pub fn update_game(game: &mut GameState, input: &Input, dt: f32) -> Option<Event> {
     update_camera(game);
+    rod::update_debug_teleport(&mut game.objects.balls, &game.physics);
+    game.previous = Some(game.objects.clone());
 
-    help::update_help(game, input);
-    rod::update_actuators(&mut game.objects.actuators, input, dt);
-    rod::update_rod_physics(&mut game.objects.balls, &game.objects.actuators);
-    return_if_some!(level::update_level(game));
-    balls::update_balls(&mut game.objects.balls, dt);
-    None
+    let mut debug = storage::get_mut::<DebugChannel>();
+    sim::step(game, input, dt, &mut debug)
 }
 
-pub fn update_camera(game: &GameState) {
+/// Camera showing the whole playfield, extended to fill the window's aspect ratio
+pub fn game_camera(game: &GameState) -> Camera2D {
     let scale = (screen_width() / screen_height()) / (SCREEN_W / SCREEN_H);
     let (w, h) = if scale >= 1.0 {
         (SCREEN_W * scale, SCREEN_H)
@@ -31,21 +32,30 @@ pub fn update_camera(game: &GameState) {
         (SCREEN_W, SCREEN_H / scale)
     };
     let target = vec2(SCREEN_W / 2., h / 2.);
-    set_camera(&Camera2D {
+    Camera2D {
         target,
         zoom: vec2(1.0 / w * 2.0, -1.0 / h * 2.0),
         offset: vec2(0., 0.),
         rotation: game.camera.rotation / 3.14 * 180.0,
         render_target: None,
         viewport: None,
-    });
+    }
 }
 
-pub fn draw_game(game: &GameState) {
-    let rod_angle = rod::rod_angle(&game.objects.actuators);
+pub fn update_camera(game: &GameState) {
+    set_camera(&game_camera(game));
+}
+
+pub fn draw_game(game: &GameState, alpha: f32) {
+    let objects = match &game.previous {
+        Some(previous) => previous.lerp(&game.objects, alpha),
+        None => game.objects.clone(),
+    };
+    let rod_angle = rod::rod_angle(&objects.actuators);
     level::draw_background(game);
-    level::draw_holes(game);
-    balls::draw_balls(&game.objects.balls, rod_angle);
-    rod::draw_rod(&game.objects.actuators, rod_angle);
+    level::draw_holes(game, &objects.holes);
+    obstacles::draw_obstacles(game);
+    balls::draw_balls(&objects.balls, &game.physics);
+    rod::draw_rod(&objects.actuators, rod_angle, &game.physics);
     help::draw_help(game);
 }
@@ -1,4 +1,7 @@
-use crate::{config::SCALE, input::Input, resources::Resources, state::GameState};
+use crate::{
+    bindings::Action, config::SCALE, input::Input, resources::Resources, settings::Settings,
+    state::GameState,
+};
 use macroquad::{experimental::collections::storage, prelude::*};
 
 pub fn update_help(game: &mut GameState, input: &Input) {
@@ -12,36 +15,42 @@ pub fn update_help(game: &mut GameState, input: &Input) {
 
 pub fn draw_help(game: &GameState) {
     let resources = storage::get::<Resources>();
+    let settings = storage::get::<Settings>();
+    let bindings = &settings.bindings;
     let blinking = (get_time() * 5.).round().rem_euclid(2.) as u32 == 0;
     if blinking && game.progress.show_help {
         draw_key(
             &resources,
             (game.objects.actuators[0].pos.x) * SCALE - 30.,
             game.objects.actuators[0].pos.y * SCALE - 110.,
-            "w",
+            &bindings.label(Action::LeftUp),
         );
         draw_key(
             &resources,
             (game.objects.actuators[0].pos.x) * SCALE - 30.,
             game.objects.actuators[0].pos.y * SCALE,
-            "s",
+            &bindings.label(Action::LeftDown),
         );
         draw_key(
             &resources,
             (game.objects.actuators[1].pos.x) * SCALE - 70.,
             game.objects.actuators[1].pos.y * SCALE - 110.,
-            "^",
+            &bindings.label(Action::RightUp),
         );
         draw_key(
             &resources,
             (game.objects.actuators[1].pos.x) * SCALE - 70.,
             game.objects.actuators[1].pos.y * SCALE,
-            "v",
+            &bindings.label(Action::RightDown),
         );
     }
 }
 
 fn draw_key(resources: &Resources, x: f32, y: f32, character: &str) {
     draw_texture(resources.key, x, y, WHITE);
-    draw_text(character, x + 34., y + 50., 64., BLACK);
+    // Longer key names get a smaller font to fit on the key
+    match character.chars().count() {
+        1 => draw_text(character, x + 34., y + 50., 64., BLACK),
+        _ => draw_text(character, x + 12., y + 50., 32., BLACK),
+    }
 }
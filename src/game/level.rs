@@ -1,26 +1,46 @@
 use crate::{
     config,
     config::SCALE,
-    debug::DebugData,
-    game::balls::BALL_RADIUS,
+    debug::{Category, DebugChannel, DebugData},
+    game::{obstacles, physics::PhysicsProfile, shapes::HoleShape},
     resources::Resources,
-    state::{Ball, Event, GameState, Hole},
+    state::{Ball, Event, GameState, Hole, HoleRole, SPAWN_HEIGHT},
 };
 use macroquad::experimental::collections::storage;
-use macroquad::math::{Vec2, Vec3};
+use macroquad::math::Vec3;
 use macroquad::prelude::*;
 
-const WALL_DAMPING: f32 = 0.4;
-const _BALL_MASS: f32 = 0.15; // kg
-
-pub fn update_level(game: &mut GameState) -> Option<Event> {
-    update_edge_physics(&mut game.objects.balls);
-    update_hole_physics(&mut game.objects.balls, &game.level.holes);
+pub fn update_level(game: &mut GameState, debug: &mut DebugChannel) -> Option<Event> {
+    update_holes(game);
+    update_edge_physics(&mut game.objects.balls, &game.physics);
+    obstacles::update_obstacle_physics(
+        &mut game.objects.balls,
+        &game.level.obstacles,
+        &game.physics,
+        debug,
+    );
+    update_hole_physics(
+        &mut game.objects.balls,
+        &game.objects.holes,
+        &game.physics,
+        debug,
+    );
     update_state(game)
 }
 
-fn update_hole_physics(balls: &mut Vec<Ball>, holes: &Vec<Hole>) {
-    let mut debug = storage::get_mut::<Vec<DebugData>>();
+/// Move, open, close and shrink holes for the current time of the round
+fn update_holes(game: &mut GameState) {
+    let t = game.progress.round_time();
+    game.objects.holes = game.level.holes.iter().map(|hole| hole.at(t)).collect();
+}
+
+fn update_hole_physics(
+    balls: &mut [Ball],
+    holes: &[Hole],
+    physics: &PhysicsProfile,
+    debug: &mut DebugChannel,
+) {
+    let radius = physics.ball_radius;
     for ball in balls.iter_mut() {
         if !ball.active {
             continue;
@@ -29,13 +49,11 @@ fn update_hole_physics(balls: &mut Vec<Ball>, holes: &Vec<Hole>) {
         for (i, hole) in holes.iter().enumerate() {
             let is_last_hole = i == holes.len() - 1;
             // Determine nearest point on hole rim
-            let edge: Vec2 = hole.pos + (ball.pos.truncate() - hole.pos).normalize() * hole.radius;
+            let (edge, rim_normal) = hole.rim(ball.pos.truncate());
 
-            // Find nearest point on wall
-            let wall = if hole.pos.distance(ball.pos.truncate()) < hole.radius - BALL_RADIUS {
-                ball.in_hole = Some(i);
-                edge.extend(f32::min(0.0, ball.pos.z))
-            } else if hole.pos.distance(ball.pos.truncate()) < hole.radius {
+            // Find nearest point on wall, closed holes are part of the background
+            let in_hole = hole.is_open() && hole.contains(ball.pos.truncate());
+            let wall = if in_hole {
                 ball.in_hole = Some(i);
                 edge.extend(f32::min(0.0, ball.pos.z))
             } else if is_last_hole && ball.in_hole.is_none() {
@@ -45,38 +63,53 @@ fn update_hole_physics(balls: &mut Vec<Ball>, holes: &Vec<Hole>) {
                 continue;
             };
 
-            debug.push(DebugData::circle(wall, 0.01, GREEN));
-            debug.push(DebugData::line(
-                Vec3::new(hole.pos.x, 0.0, 0.0),
-                Vec3::new(hole.pos.x, hole.pos.y - hole.radius, 0.0),
-                WHITE,
-            ));
-            debug.push(DebugData::line(
-                Vec3::new(hole.pos.x, hole.pos.y + hole.radius, 0.0),
-                Vec3::new(hole.pos.x, 10.0, 0.0),
-                WHITE,
-            ));
-            debug.push(DebugData::line(
-                Vec3::new(hole.pos.x, hole.pos.y - hole.radius, 0.0),
-                Vec3::new(hole.pos.x, hole.pos.y - hole.radius, -5.0),
-                WHITE,
-            ));
-            debug.push(DebugData::line(
-                Vec3::new(hole.pos.x, hole.pos.y + hole.radius, 0.0),
-                Vec3::new(hole.pos.x, hole.pos.y + hole.radius, -5.0),
-                WHITE,
-            ));
+            debug.push(Category::Holes, DebugData::circle(wall, 0.01, GREEN));
+            debug.push(
+                Category::Holes,
+                DebugData::line(
+                    Vec3::new(hole.pos.x, 0.0, 0.0),
+                    Vec3::new(hole.pos.x, hole.pos.y - hole.radius, 0.0),
+                    WHITE,
+                ),
+            );
+            debug.push(
+                Category::Holes,
+                DebugData::line(
+                    Vec3::new(hole.pos.x, hole.pos.y + hole.radius, 0.0),
+                    Vec3::new(hole.pos.x, 10.0, 0.0),
+                    WHITE,
+                ),
+            );
+            debug.push(
+                Category::Holes,
+                DebugData::line(
+                    Vec3::new(hole.pos.x, hole.pos.y - hole.radius, 0.0),
+                    Vec3::new(hole.pos.x, hole.pos.y - hole.radius, -5.0),
+                    WHITE,
+                ),
+            );
+            debug.push(
+                Category::Holes,
+                DebugData::line(
+                    Vec3::new(hole.pos.x, hole.pos.y + hole.radius, 0.0),
+                    Vec3::new(hole.pos.x, hole.pos.y + hole.radius, -5.0),
+                    WHITE,
+                ),
+            );
 
             // Determine normal vectors
-            let unit_normal = (hole.pos - edge).extend(0.0).normalize();
+            let unit_normal = match in_hole {
+                true => (-rim_normal).extend(0.0),
+                false => Vec3::new(1.0, 0.0, 0.0),
+            };
             let wall_normal = (ball.pos - wall).normalize();
 
             // Solve collision by moving ball in normal direction
             let distance: Vec3 = ball.pos - wall;
             let sgn = unit_normal.dot(distance).signum();
-            let intrusion: f32 = ball.pos.distance(wall) - BALL_RADIUS;
+            let intrusion: f32 = ball.pos.distance(wall) - radius;
 
-            if (wall.distance(ball.pos) < BALL_RADIUS) && sgn > 0.0 {
+            if (wall.distance(ball.pos) < radius) && sgn > 0.0 {
                 ball.pos -= wall_normal * intrusion * sgn;
             } else {
                 continue;
@@ -89,34 +122,40 @@ fn update_hole_physics(balls: &mut Vec<Ball>, holes: &Vec<Hole>) {
             // Debug
             let projection = Vec3::new(-wall_normal.y, wall_normal.x, unit_normal.z);
 
-            debug.push(DebugData::line(
-                ball.pos,
-                ball.pos + projection * 0.2,
-                YELLOW,
-            ));
-            debug.push(DebugData::line(wall, wall + wall_normal * 0.2, MAGENTA));
+            debug.push(
+                Category::Holes,
+                DebugData::line(ball.pos, ball.pos + projection * 0.2, YELLOW),
+            );
+            debug.push(
+                Category::Holes,
+                DebugData::line(wall, wall + wall_normal * 0.2, MAGENTA),
+            );
         }
 
-        debug.push(DebugData::text("in hole", format!("{:?}", ball.in_hole)));
+        debug.push(
+            Category::Holes,
+            DebugData::text("in hole", format!("{:?}", ball.in_hole)),
+        );
     }
 }
 
-fn update_edge_physics(balls: &mut Vec<Ball>) {
+fn update_edge_physics(balls: &mut [Ball], physics: &PhysicsProfile) {
+    let radius = physics.ball_radius;
     for ball in balls.iter_mut() {
         if !ball.active {
             continue;
         }
         // X-axis
-        let min_x = BALL_RADIUS;
-        let max_x = config::SCREEN_W / SCALE - BALL_RADIUS;
+        let min_x = radius;
+        let max_x = config::SCREEN_W / SCALE - radius;
         if ball.pos.x < min_x || ball.pos.x > max_x {
             ball.pos.x = ball.pos.x.clamp(min_x, max_x);
-            let impulse = Vec3::new(-ball.vel.x * (1.0 + WALL_DAMPING), 0.0, 0.0); // * mass
+            let impulse = Vec3::new(-ball.vel.x * (1.0 + physics.wall_damping), 0.0, 0.0); // * mass
             ball.impulses.push(impulse);
         }
         // Z-axis
-        let min_z = -3.0 * BALL_RADIUS;
-        let max_z = BALL_RADIUS;
+        let min_z = -3.0 * radius;
+        let max_z = radius;
         if ball.pos.z < min_z || ball.pos.z > max_z {
             ball.pos.z = ball.pos.z.clamp(min_z, max_z);
             ball.impulses.push(Vec3::new(0.0, 0.0, -ball.vel.z));
@@ -125,45 +164,142 @@ fn update_edge_physics(balls: &mut Vec<Ball>) {
 }
 
 fn update_state(game: &mut GameState) -> Option<Event> {
-    for ball in game.objects.balls.iter_mut() {
-        if !ball.active {
+    let goal_hole = game.get_goal_hole();
+    let mut dropped = false;
+    let mut goal_reached = false;
+    for i in 0..game.objects.balls.len() {
+        let ball = &game.objects.balls[i];
+        if !ball.active || ball.pos.z >= -2. * game.physics.ball_radius {
             continue;
         }
-        if ball.pos.z < -2. * BALL_RADIUS {
-            if let Some(current_hole) = ball.in_hole {
-                ball.active = false;
-                if game.get_goal_hole() == current_hole {
-                    if game.progress.goal_index >= game.level.goals.len() - 1 {
-                        return Some(Event::GameCompleted);
-                    } else {
-                        return Some(Event::RoundCompleted);
-                    }
-                } else {
-                    if game.progress.balls_left < 1 {
-                        return Some(Event::GameEnded);
-                    } else {
-                        game.progress.balls_left -= 1;
-                        return Some(Event::RoundLost);
-                    }
-                }
+        let current_hole = match ball.in_hole {
+            Some(current_hole) => current_hole,
+            None => continue,
+        };
+        if current_hole == goal_hole {
+            game.objects.balls[i].active = false;
+            goal_reached = true;
+            continue;
+        }
+        let role = &game.level.holes[current_hole].role;
+        if !role.is_normal() {
+            // Special holes score and put the ball back onto the rod
+            role.apply(&mut game.progress);
+            if let Some(ball) = respawn_ball(game, i) {
+                game.objects.balls[i] = ball;
+                continue;
             }
         }
+        game.objects.balls[i].active = false;
+        dropped = true;
+    }
+    // Other balls that dropped in the same step still count
+    if goal_reached {
+        if game.progress.goal_index >= game.level.goals.len() - 1 {
+            return Some(Event::GameCompleted);
+        } else {
+            return Some(Event::RoundCompleted);
+        }
+    }
+    // A ball in the wrong hole is out, the round is lost with the last one
+    if dropped && game.objects.balls.iter().all(|ball| !ball.active) {
+        if game.progress.balls_left < 1 {
+            return Some(Event::GameEnded);
+        } else {
+            game.progress.balls_left -= 1;
+            return Some(Event::RoundLost);
+        }
     }
     None
 }
 
-pub fn draw_holes(game: &GameState) {
-    for (i, hole) in game.level.holes.iter().enumerate() {
+/// A new ball for ball `index` on top of the rod, as close to its middle as
+/// possible without touching other balls or being over an open hole; None if
+/// there is no room left on the rod
+fn respawn_ball(game: &GameState, index: usize) -> Option<Ball> {
+    let [left, right] = &game.objects.actuators;
+    let spacing = game.physics.ball_radius * 3.;
+    let middle = (left.pos.x + right.pos.x) / 2.;
+    let slots = ((middle - left.pos.x) / spacing) as i32;
+    (0..slots)
+        .flat_map(|slot| [slot, -slot])
+        .map(|slot| {
+            let mut ball = Ball::new(&game.physics);
+            let x = middle + slot as f32 * spacing;
+            let along = (x - left.pos.x) / (right.pos.x - left.pos.x);
+            ball.pos.x = x;
+            ball.pos.y = left.pos.y + along * (right.pos.y - left.pos.y) - SPAWN_HEIGHT;
+            ball
+        })
+        .find(|ball| {
+            let pos = ball.pos.truncate();
+            let over_hole = |hole: &Hole| hole.is_open() && hole.contains(pos);
+            let touching = |(i, other): (usize, &Ball)| {
+                i != index && other.active && other.pos.truncate().distance(pos) < spacing
+            };
+            !game.objects.holes.iter().any(over_hole)
+                && !game.objects.balls.iter().enumerate().any(touching)
+        })
+}
+
+pub fn draw_holes(game: &GameState, holes: &[Hole]) {
+    for (i, hole) in holes.iter().enumerate().filter(|(_, hole)| hole.is_open()) {
         let color = match game.get_goal_hole() == i {
             true => YELLOW,
             false => BLACK,
         };
-        draw_circle(
+        draw_hole(hole, color);
+        let role_color = match hole.role {
+            HoleRole::Normal => continue,
+            HoleRole::Bonus { .. } => GREEN,
+            HoleRole::Penalty { .. } => RED,
+            HoleRole::ExtraBall => BLUE,
+            HoleRole::Multiplier { .. } => PURPLE,
+        };
+        draw_hole_lines(hole, 8., role_color);
+    }
+}
+
+pub fn draw_hole(hole: &Hole, color: Color) {
+    match hole.shape {
+        HoleShape::Circle => draw_circle(
             hole.pos.x * SCALE,
             hole.pos.y * SCALE,
             hole.radius * SCALE,
             color,
-        );
+        ),
+        _ => {
+            // Fan around the hole position, which sees the whole rim
+            let outline = hole.outline();
+            for (a, b) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+                draw_triangle(hole.pos * SCALE, *a * SCALE, *b * SCALE, color);
+            }
+        }
+    }
+}
+
+pub fn draw_hole_lines(hole: &Hole, thickness: f32, color: Color) {
+    match hole.shape {
+        HoleShape::Circle => draw_circle_lines(
+            hole.pos.x * SCALE,
+            hole.pos.y * SCALE,
+            hole.radius * SCALE,
+            thickness,
+            color,
+        ),
+        _ => {
+            let outline = hole.outline();
+            for (a, b) in outline.iter().zip(outline.iter().cycle().skip(1)) {
+                draw_line(
+                    a.x * SCALE,
+                    a.y * SCALE,
+                    b.x * SCALE,
+                    b.y * SCALE,
+                    thickness,
+                    color,
+                );
+            }
+        }
     }
 }
 
@@ -183,3 +319,137 @@ pub fn draw_background(game: &GameState) {
         },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::sim::Simulation, input::Input, timestep::FIXED_DELTATIME};
+    use macroquad::math::Vec2;
+
+    const RAISE: Input = Input {
+        actuators: [1., 1.],
+        menu_up: false,
+        menu_down: false,
+        enter: false,
+        escape: false,
+    };
+
+    fn bonus_hole(pos: Vec2) -> Hole {
+        Hole {
+            pos,
+            radius: 0.05,
+            role: HoleRole::Bonus { points: 10 },
+            ..Default::default()
+        }
+    }
+
+    fn run(sim: &mut Simulation, input: &Input, steps: usize) -> Vec<Event> {
+        (0..steps)
+            .filter_map(|_| sim.step(input, FIXED_DELTATIME))
+            .collect()
+    }
+
+    #[test]
+    fn bonus_hole_puts_ball_back_on_raised_rod() {
+        let mut game = GameState::new();
+        game.level.holes = vec![
+            Hole {
+                pos: Vec2::new(0.5, 0.2),
+                radius: 0.05,
+                ..Default::default()
+            },
+            bonus_hole(Vec2::new(0.2, 1.4)),
+        ];
+        game.level.goals = vec![0];
+        let mut sim = Simulation::new(game);
+        assert_eq!(run(&mut sim, &RAISE, 300), vec![]);
+        let rod_y = sim.game.objects.actuators[0].pos.y;
+        assert!(rod_y < 1.4, "rod at {}", rod_y);
+
+        let radius = sim.game.physics.ball_radius;
+        sim.game.objects.balls[0].pos = Vec2::new(0.2, 1.4).extend(-3. * radius);
+        assert_eq!(run(&mut sim, &Input::default(), 500), vec![]);
+        assert_eq!(sim.game.progress.score, 10);
+        let ball = &sim.game.objects.balls[0];
+        assert!(ball.active);
+        assert!((ball.pos.y - rod_y).abs() < 0.1, "ball at {}", ball.pos);
+    }
+
+    #[test]
+    fn round_is_lost_without_room_to_respawn() {
+        let mut game = GameState::new();
+        // Holes all along the rod where balls would be put back
+        let spawn_y = (config::SCREEN_H - 100.) / SCALE - SPAWN_HEIGHT;
+        game.level.holes = (0..12)
+            .map(|i| Hole {
+                pos: Vec2::new(0.04 + i as f32 * 0.09, spawn_y),
+                radius: 0.05,
+                ..Default::default()
+            })
+            .collect();
+        game.level.holes.push(bonus_hole(Vec2::new(0.5, 1.2)));
+        game.level.goals = vec![0];
+        game.progress.balls_left = 1;
+        let mut sim = Simulation::new(game);
+
+        let radius = sim.game.physics.ball_radius;
+        sim.game.objects.balls[0].pos = Vec2::new(0.5, 1.2).extend(-3. * radius);
+        assert_eq!(run(&mut sim, &Input::default(), 10), vec![Event::RoundLost]);
+        assert_eq!(sim.game.progress.score, 10);
+    }
+
+    #[test]
+    fn balls_dropping_with_the_goal_ball_still_count() {
+        let mut game = GameState::new();
+        game.level.holes = vec![
+            Hole {
+                pos: Vec2::new(0.5, 0.8),
+                radius: 0.05,
+                ..Default::default()
+            },
+            bonus_hole(Vec2::new(0.2, 1.2)),
+        ];
+        game.level.goals = vec![0, 1];
+        game.level.multiball = vec![2];
+        let mut game = game.reset_round();
+
+        // Both balls drop in the same step, the goal ball first
+        let radius = game.physics.ball_radius;
+        for (ball, hole) in game.objects.balls.iter_mut().zip([0, 1]) {
+            ball.pos = game.level.holes[hole].pos.extend(-3. * radius);
+            ball.in_hole = Some(hole);
+        }
+        assert_eq!(update_state(&mut game), Some(Event::RoundCompleted));
+        assert_eq!(game.progress.score, 10);
+        assert!(!game.objects.balls[0].active);
+        assert!(game.objects.balls[1].active);
+    }
+
+    #[test]
+    fn respawned_ball_keeps_clear_of_other_balls() {
+        let mut game = GameState::new();
+        game.level.holes = vec![
+            Hole {
+                pos: Vec2::new(0.5, 0.2),
+                radius: 0.05,
+                ..Default::default()
+            },
+            bonus_hole(Vec2::new(0.2, 1.2)),
+        ];
+        game.level.goals = vec![0];
+        game.level.multiball = vec![2];
+        let mut sim = Simulation::new(game);
+
+        // One ball waits in the middle of the rod, the other drops into the bonus hole
+        let radius = sim.game.physics.ball_radius;
+        let middle = Ball::new(&sim.game.physics).pos;
+        sim.game.objects.balls[1].pos = middle;
+        sim.game.objects.balls[0].pos = Vec2::new(0.2, 1.2).extend(-3. * radius);
+        assert_eq!(run(&mut sim, &Input::default(), 1), vec![]);
+        assert_eq!(sim.game.progress.score, 10);
+        let [first, second] = [&sim.game.objects.balls[0], &sim.game.objects.balls[1]];
+        assert!(first.active && second.active);
+        // Not overlapping, so the collision does not throw them apart
+        assert!(first.pos.distance(second.pos) > 2.5 * radius);
+    }
+}
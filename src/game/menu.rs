@@ -2,7 +2,7 @@ use crate::{
     config::{SCREEN_H, SCREEN_W},
     input::Input,
     resources::{Resources, SoundResources},
-    state::{Event, MenuState},
+    state::{Event, MenuKind, MenuState},
     utils::draw_centered_text,
 };
 use macroquad::{
@@ -32,14 +32,15 @@ pub fn update_menu(menu: &mut MenuState, input: &Input) -> Option<Event> {
         menu.selected = (menu.selected + 1).rem_euclid(menu.options.len());
     }
 
-    if input.enter {
-        return Some(Event::MenuSelected(
-            menu.options
-                .get(menu.selected)
-                .unwrap()
-                .to_string()
-                .to_lowercase(),
-        ));
+    if input.escape && menu.kind == MenuKind::Levels {
+        return Some(Event::MenuSelected("back".to_string()));
+    }
+    if input.enter && !menu.disabled.contains(&menu.selected) {
+        let option = menu.options.get(menu.selected).unwrap().to_lowercase();
+        return match menu.kind {
+            MenuKind::Levels if option != "back" => Some(Event::LevelSelected(menu.selected)),
+            _ => Some(Event::MenuSelected(option)),
+        };
     }
     None
 }
@@ -69,7 +70,11 @@ pub fn draw_menu(menu: &MenuState) {
     let y_start = SCREEN_H / 2. - 80.;
     for (i, text) in menu.options.iter().enumerate() {
         let font_size = FONT_SIZE_MENU + scale * ((menu.selected == i) as i32 as f32);
-        let text_params = get_text_params(&resources, font_size, WHITE);
+        let color = match menu.disabled.contains(&i) {
+            true => GRAY,
+            false => WHITE,
+        };
+        let text_params = get_text_params(&resources, font_size, color);
         if menu.selected == i {
             draw_centered_text(
                 text,
@@ -88,7 +93,7 @@ pub fn draw_menu(menu: &MenuState) {
     }
 }
 
-fn get_text_params(resources: &Resources, font_size: f32, color: Color) -> TextParams {
+pub fn get_text_params(resources: &Resources, font_size: f32, color: Color) -> TextParams {
     TextParams {
         font_size: font_size as u16,
         font: resources.font_menu,
@@ -1,6 +1,13 @@
+pub mod autopilot;
 pub mod balls;
+pub mod controls;
 pub mod game;
 pub mod help;
 pub mod level;
 pub mod menu;
+pub mod obstacles;
+pub mod physics;
 pub mod rod;
+pub mod scores;
+pub mod shapes;
+pub mod sim;
@@ -0,0 +1,130 @@
+use crate::{
+    config::SCALE,
+    debug::{Category, DebugChannel, DebugData},
+    game::{game::DARKGRAY_SHADOW, physics::PhysicsProfile},
+    state::{Ball, GameState, Obstacle, WALL_THICKNESS},
+};
+use macroquad::prelude::*;
+
+pub fn update_obstacle_physics(
+    balls: &mut [Ball],
+    obstacles: &[Obstacle],
+    physics: &PhysicsProfile,
+    debug: &mut DebugChannel,
+) {
+    for ball in balls.iter_mut() {
+        // Obstacles stand on the playfield, a ball sinking into a hole passes below them
+        if !ball.active || ball.pos.z < 0. {
+            continue;
+        }
+        for obstacle in obstacles.iter() {
+            let (nearest, radius) = obstacle.nearest(ball.pos.truncate());
+            let delta = (ball.pos.truncate() - nearest).extend(0.0);
+            let distance = delta.length();
+            let contact_distance = radius + physics.ball_radius;
+            if distance >= contact_distance || distance == 0. {
+                continue;
+            }
+            // Solve collision by moving ball in normal direction
+            let normal = delta / distance;
+            ball.pos += normal * (contact_distance - distance);
+
+            // Reflect the approaching velocity, bumpers kick the ball away
+            let normal_vel = normal.dot(ball.vel);
+            if normal_vel < 0. {
+                ball.impulses
+                    .push(-normal * normal_vel * (1.0 + physics.wall_damping));
+            }
+            if let Obstacle::Bumper { kick, .. } = obstacle {
+                ball.impulses.push(normal * *kick);
+            }
+
+            // Debug
+            let contact = nearest.extend(ball.pos.z) + normal * radius;
+            debug.push(
+                Category::Obstacles,
+                DebugData::circle(contact, 0.01, ORANGE),
+            );
+            debug.push(
+                Category::Obstacles,
+                DebugData::line(contact, contact + normal * 0.2, ORANGE),
+            );
+        }
+    }
+}
+
+pub fn draw_obstacles(game: &GameState) {
+    for obstacle in game.level.obstacles.iter() {
+        draw_obstacle(obstacle, 1.);
+    }
+}
+
+pub fn draw_obstacle(obstacle: &Obstacle, alpha: f32) {
+    let shadow = Vec2::new(8., 3.);
+    match obstacle {
+        Obstacle::Post { pos, radius } => {
+            let center = *pos * SCALE;
+            draw_circle(
+                center.x + shadow.x,
+                center.y + shadow.y,
+                radius * SCALE,
+                DARKGRAY_SHADOW,
+            );
+            draw_circle(
+                center.x,
+                center.y,
+                radius * SCALE,
+                Color::new(0.6, 0.6, 0.6, alpha),
+            );
+        }
+        Obstacle::Bumper { pos, radius, .. } => {
+            let center = *pos * SCALE;
+            draw_circle(
+                center.x + shadow.x,
+                center.y + shadow.y,
+                radius * SCALE,
+                DARKGRAY_SHADOW,
+            );
+            draw_circle(
+                center.x,
+                center.y,
+                radius * SCALE,
+                Color::new(0.8, 0.1, 0.1, alpha),
+            );
+            draw_circle_lines(
+                center.x,
+                center.y,
+                radius * SCALE,
+                6.,
+                Color::new(1., 0.8, 0.2, alpha),
+            );
+        }
+        Obstacle::Wall { from, to } => {
+            let (from, to) = (*from * SCALE, *to * SCALE);
+            let thickness = WALL_THICKNESS * SCALE;
+            draw_line(
+                from.x + shadow.x,
+                from.y + shadow.y,
+                to.x + shadow.x,
+                to.y + shadow.y,
+                thickness,
+                DARKGRAY_SHADOW,
+            );
+            draw_line(
+                from.x,
+                from.y,
+                to.x,
+                to.y,
+                thickness,
+                Color::new(0.6, 0.6, 0.6, alpha),
+            );
+            draw_circle(
+                from.x,
+                from.y,
+                thickness / 2.,
+                Color::new(0.6, 0.6, 0.6, alpha),
+            );
+            draw_circle(to.x, to.y, thickness / 2., Color::new(0.6, 0.6, 0.6, alpha));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    resources::{load_asset, AssetSource},
+    Result,
+};
+use macroquad::math::Vec3;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+};
+
+pub const PHYSICS_FILE: &str = "physics.json";
+
+/// Tunable physics parameters, loaded from `PHYSICS_FILE`
+/// with optional per level overrides
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsProfile {
+    pub gravity: Vec3, // m/s^2
+    pub wall_damping: f32,
+    pub actuator_vel: f32, // m/s
+    pub actuator_stiffness: f32,
+    pub actuator_damping: f32,
+    pub ball_radius: f32, // m
+    pub ball_mass: f32,   // kg
+    pub ball_restitution: f32,
+    pub ball_friction: f32,
+    /// Fraction of spin lost per second to rolling resistance
+    pub rolling_resistance: f32,
+    pub rod_radius: f32, // m
+    pub rod_friction: f32,
+}
+
+impl Default for PhysicsProfile {
+    fn default() -> Self {
+        PhysicsProfile {
+            gravity: Vec3::new(0.0, 9.81, -0.1),
+            wall_damping: 0.4,
+            actuator_vel: 2.0, // TODO does not match reality
+            actuator_stiffness: 0.9,
+            actuator_damping: 8.0,
+            ball_radius: 0.03,
+            ball_mass: 0.15,
+            ball_restitution: 0.7,
+            ball_friction: 0.2,
+            rolling_resistance: 0.4,
+            rod_radius: 0.008,
+            rod_friction: 0.6,
+        }
+    }
+}
+
+impl PhysicsProfile {
+    /// Load the base profile and apply a level's `overrides` on top of it
+    pub fn load(overrides: &Map<String, Value>) -> Result<Self> {
+        let mut profile = match load_asset(PHYSICS_FILE) {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => serde_json::to_value(PhysicsProfile::default())?,
+        };
+        if let Value::Object(fields) = &mut profile {
+            fields.extend(overrides.clone());
+        }
+        Ok(serde_json::from_value(profile)?)
+    }
+
+    /// Save to the user levels directory, where it overrides the compiled in profile
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = AssetSource::user_levels_dir().ok_or("No user data directory")?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(PHYSICS_FILE);
+        serde_json::to_writer_pretty(File::create(&path)?, self)?;
+        Ok(path)
+    }
+
+    /// Moment of inertia of a solid sphere, 2/5 m r^2
+    pub fn ball_inertia(&self) -> f32 {
+        0.4 * self.ball_mass * self.ball_radius * self.ball_radius
+    }
+
+    /// Height of the rod above the playfield
+    pub fn actuator_z(&self) -> f32 {
+        self.ball_radius * 1.2
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
     config::{SCALE, SCREEN_W},
-    debug::DebugData,
-    game::balls::BALL_RADIUS,
+    debug::{Category, DebugChannel, DebugData, TuningPanel},
+    game::physics::PhysicsProfile,
     input::Input,
+    replay,
     resources::Resources,
     state::{Actuator, Ball},
 };
@@ -13,22 +14,23 @@ use macroquad::{
     prelude::*,
 };
 
-const ACTUATOR_VEL: f32 = 2.0; // m/s TODO does not match reality
-const ACTUATOR_STIFFNESS: f32 = 0.9;
-const ACTUATOR_DAMPING: f32 = 8.0;
 pub const ACTUATOR_WIDTH: f32 = 100. / SCALE; // px / scale
-pub const ACTUATOR_Z: f32 = BALL_RADIUS * 1.2;
-pub const ROD_RADIUS: f32 = 0.008;
 
-pub fn update_actuators(actuators: &mut [Actuator; 2], input: &Input, dt: f32) {
+pub fn update_actuators(
+    actuators: &mut [Actuator; 2],
+    input: &Input,
+    physics: &PhysicsProfile,
+    dt: f32,
+) {
     let actuator_y_mean = (actuators[0].pos.y + actuators[1].pos.y) / 2.0;
     for (actuator, actuator_input) in actuators.iter_mut().zip(input.actuators) {
-        let target_vel = actuator_input * ACTUATOR_VEL;
+        let target_vel = actuator_input * physics.actuator_vel;
         let delta_vel = target_vel - actuator.vel;
 
         // Actuator velocity follows Hooke's law F = -k*x - b*v
         // where k and b are stiffness and damping constants
-        actuator.vel += dt * (-ACTUATOR_STIFFNESS * delta_vel - actuator.vel * ACTUATOR_DAMPING);
+        actuator.vel += dt
+            * (-physics.actuator_stiffness * delta_vel - actuator.vel * physics.actuator_damping);
         actuator.pos.y += dt * actuator.vel;
         // Clamp distance
         actuator.pos.y = actuator
@@ -38,20 +40,36 @@ pub fn update_actuators(actuators: &mut [Actuator; 2], input: &Input, dt: f32) {
     }
 }
 
-pub fn update_rod_physics(balls: &mut Vec<Ball>, actuators: &[Actuator; 2]) {
-    let mut debug = storage::get_mut::<Vec<DebugData>>();
+/// Move the ball to the mouse while the debug overlay is open; otherwise
+/// the mouse drives the touch sliders. Off while recording, replays only
+/// store inputs.
+pub fn update_debug_teleport(balls: &mut [Ball], physics: &PhysicsProfile) {
+    let debugging = storage::try_get::<TuningPanel>().is_some_and(|panel| panel.visible);
+    if !debugging || replay::is_recording() || !is_mouse_button_down(MouseButton::Left) {
+        return;
+    }
+    for ball in balls.iter_mut().filter(|ball| ball.active) {
+        ball.pos = (Vec2::from(mouse_position()) / SCALE * 2.0).extend(physics.ball_radius);
+        ball.vel = Vec3::new(0.0, 0.0, 0.0);
+        ball.spin = Vec3::new(0.0, 0.0, 0.0);
+    }
+}
+
+pub fn update_rod_physics(
+    balls: &mut [Ball],
+    actuators: &[Actuator; 2],
+    physics: &PhysicsProfile,
+    debug: &mut DebugChannel,
+) {
+    let contact_distance = physics.ball_radius + physics.rod_radius;
     for ball in balls.iter_mut() {
         if !ball.active {
             continue;
         }
-        if is_mouse_button_down(MouseButton::Left) {
-            ball.pos = (Vec2::from(mouse_position()) / SCALE * 2.0).extend(BALL_RADIUS);
-            ball.vel = Vec3::new(0.0, 0.0, 0.0);
-        }
-        debug.push(DebugData::circle(ball.pos, 0.03, BLUE));
+        debug.push(Category::Balls, DebugData::circle(ball.pos, 0.03, BLUE));
 
         // Determine nearest point on rod
-        let actuator = actuators[0].pos.extend(ACTUATOR_Z);
+        let actuator = actuators[0].pos.extend(physics.actuator_z());
         let rod = actuator
             + seesaw_unit_vec(actuators) * (ball.pos - actuator).dot(seesaw_unit_vec(actuators));
         // Determine normal vectors
@@ -60,23 +78,44 @@ pub fn update_rod_physics(balls: &mut Vec<Ball>, actuators: &[Actuator; 2]) {
         // Solve collision by moving ball in normal direction
         let distance: Vec3 = ball.pos - rod;
         let sgn = Vec3::new(0.0, -1.0, 0.0).dot(distance).signum();
-        let intrusion: f32 = ball.pos.distance(rod) - (BALL_RADIUS + ROD_RADIUS);
-        if (rod.distance(ball.pos) < (BALL_RADIUS + ROD_RADIUS)) && sgn > 0.0 {
+        let intrusion: f32 = ball.pos.distance(rod) - contact_distance;
+        if (rod.distance(ball.pos) < contact_distance) && sgn > 0.0 {
             ball.pos -= rod_normal * intrusion * sgn;
         } else {
             continue;
         }
 
         // Correct velocity
-        ball.impulses.push(-rod_normal.dot(ball.vel) * rod_normal);
+        let normal_vel = rod_normal.dot(ball.vel);
+        ball.impulses.push(-normal_vel * rod_normal);
+
+        // Friction at the contact point drives the ball towards rolling without slipping,
+        // limited by the Coulomb cone around the normal impulse
+        let contact = -rod_normal * physics.ball_radius;
+        let contact_vel = ball.vel + ball.spin.cross(contact);
+        let slip = contact_vel - rod_normal * contact_vel.dot(rod_normal);
+        let slip_speed = slip.length();
+        if slip_speed > f32::EPSILON {
+            // Effective mass of a solid sphere at its rim: 1 / (1/m + r^2/I) = 2m/7
+            let mass = physics.ball_mass;
+            let stop_impulse = slip_speed * mass * 2. / 7.;
+            let max_impulse = physics.rod_friction * mass * normal_vel.abs();
+            let friction = -slip / slip_speed * f32::min(stop_impulse, max_impulse);
+            ball.impulses.push(friction / mass);
+            ball.angular_impulses
+                .push(contact.cross(friction) / physics.ball_inertia());
+        }
         // Debug
-        debug.push(DebugData::text("rod hit", "hit".to_string()));
-        debug.push(DebugData::line(rod, rod + rod_normal * 0.2, RED));
-        debug.push(DebugData::circle(rod, 0.01, RED));
+        debug.push(Category::Rod, DebugData::text("rod hit", "hit".to_string()));
+        debug.push(
+            Category::Rod,
+            DebugData::line(rod, rod + rod_normal * 0.2, RED),
+        );
+        debug.push(Category::Rod, DebugData::circle(rod, 0.01, RED));
     }
 }
 
-pub fn draw_rod(actuators: &[Actuator; 2], rod_angle: f32) {
+pub fn draw_rod(actuators: &[Actuator; 2], rod_angle: f32, physics: &PhysicsProfile) {
     let resources = storage::get_mut::<Resources>();
     // Actuators
 
@@ -105,10 +144,10 @@ pub fn draw_rod(actuators: &[Actuator; 2], rod_angle: f32) {
     draw_texture_ex(
         resources.rod,
         rod_pos.x * SCALE - rod_length / 2.,
-        rod_pos.y * SCALE - ROD_RADIUS * SCALE,
+        rod_pos.y * SCALE - physics.rod_radius * SCALE,
         WHITE,
         DrawTextureParams {
-            dest_size: Some(Vec2::new(rod_length, ROD_RADIUS * 2. * SCALE)),
+            dest_size: Some(Vec2::new(rod_length, physics.rod_radius * 2. * SCALE)),
             rotation: rod_angle,
             ..Default::default()
         },
@@ -134,3 +173,52 @@ fn interp_cubic_ease(p: f32) -> f32 {
         p => 1. - f32::powf(-2. * p + 2., 3.) / 2.,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::sim::Simulation,
+        state::{GameState, Hole},
+        timestep::FIXED_DELTATIME,
+    };
+
+    #[test]
+    fn ball_on_tilted_rod_starts_rolling_without_slipping() {
+        let mut game = GameState::new();
+        game.level.holes = vec![Hole {
+            pos: Vec2::new(0.5, 0.2),
+            radius: 0.05,
+            ..Default::default()
+        }];
+        game.level.goals = vec![0];
+        let mut sim = Simulation::new(game);
+        // Right end lowered below the ball, which drops on the rod and rolls right
+        sim.game.objects.actuators[1].pos.y += 0.1;
+
+        // Speed of the ball along the rod, and of its rim turning over the rod;
+        // the two are equal when it rolls without slipping
+        let speeds = |sim: &Simulation| {
+            let (ball, actuators) = (&sim.game.objects.balls[0], &sim.game.objects.actuators);
+            let along = seesaw_unit_vec(actuators);
+            let start = actuators[0].pos.extend(sim.game.physics.actuator_z());
+            let rod = start + along * (ball.pos - start).dot(along);
+            let contact = (rod - ball.pos).normalize() * sim.game.physics.ball_radius;
+            (ball.vel.dot(along), -ball.spin.cross(contact).dot(along))
+        };
+        let mut slips = vec![];
+        for _ in 0..12 {
+            for _ in 0..10 {
+                assert_eq!(sim.step(&Input::default(), FIXED_DELTATIME), None);
+            }
+            let (vel, rim) = speeds(&sim);
+            slips.push((vel - rim).abs() / vel);
+        }
+        let (vel, _) = speeds(&sim);
+        assert!(vel > 0.5, "ball rolls at {}", vel);
+        // Released without spin, then rolling once friction caught up,
+        // with only the last step's acceleration left to catch up with
+        assert!(slips[0] > 0.5, "{:?}", slips);
+        assert!(slips.last().unwrap() < &0.02, "{:?}", slips);
+    }
+}
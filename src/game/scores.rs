@@ -0,0 +1,107 @@
+use super::menu::get_text_params;
+use crate::{
+    config::{SCREEN_H, SCREEN_W},
+    input::Input,
+    resources::Resources,
+    state::{Event, HighScoreState, InitialsState},
+    utils::draw_centered_text,
+};
+use macroquad::{experimental::collections::storage, prelude::*};
+
+const FONT_SIZE_TITLE: f32 = 112.;
+const FONT_SIZE_SCORE: f32 = 80.;
+const LETTERS: u8 = 26;
+
+pub fn update_initials(initials: &mut InitialsState, input: &Input) -> Option<Event> {
+    if initials.position >= initials.letters.len() {
+        return None;
+    }
+    let letter = &mut initials.letters[initials.position];
+    if input.menu_up {
+        *letter = (*letter + 1) % LETTERS;
+    } else if input.menu_down {
+        *letter = (*letter + LETTERS - 1) % LETTERS;
+    }
+    if input.enter {
+        initials.position += 1;
+        if initials.position == initials.letters.len() {
+            return Some(Event::InitialsEntered);
+        }
+    }
+    None
+}
+
+pub fn update_scores(scores: &mut HighScoreState, input: &Input) -> Option<Event> {
+    let tables = scores.scores.tables.len().max(1);
+    if input.menu_up {
+        scores.selected = (scores.selected + tables - 1) % tables;
+    } else if input.menu_down {
+        scores.selected = (scores.selected + 1) % tables;
+    }
+    if input.enter || input.escape {
+        return Some(Event::ScoresClosed);
+    }
+    None
+}
+
+pub fn draw_initials(initials: &InitialsState) {
+    let resources = storage::get::<Resources>();
+    let y_start = SCREEN_H / 2. - 80.;
+    let score = initials.pending.iter().map(|p| p.score).max().unwrap_or(0);
+    draw_centered_text(
+        "high score",
+        SCREEN_W / 2.,
+        y_start - FONT_SIZE_TITLE,
+        get_text_params(&resources, FONT_SIZE_TITLE, WHITE),
+    );
+    draw_centered_text(
+        &score.to_string(),
+        SCREEN_W / 2.,
+        y_start,
+        get_text_params(&resources, FONT_SIZE_TITLE, WHITE),
+    );
+    let blinking = (get_time() * 5.).round().rem_euclid(2.) as u32 == 0;
+    for (i, letter) in initials.initials().chars().enumerate() {
+        let color = match i == initials.position {
+            true if blinking => Color::from_rgba(254, 93, 29, 255),
+            _ => WHITE,
+        };
+        draw_centered_text(
+            &letter.to_string(),
+            SCREEN_W / 2. + (i as f32 - 1.) * FONT_SIZE_TITLE,
+            y_start + FONT_SIZE_TITLE * 2.,
+            get_text_params(&resources, FONT_SIZE_TITLE, color),
+        );
+    }
+}
+
+pub fn draw_scores(scores: &HighScoreState) {
+    let resources = storage::get::<Resources>();
+    let y_start = SCREEN_H / 4.;
+    let table = match scores.scores.tables.values().nth(scores.selected) {
+        Some(table) => table,
+        None => {
+            draw_centered_text(
+                "no scores",
+                SCREEN_W / 2.,
+                SCREEN_H / 2.,
+                get_text_params(&resources, FONT_SIZE_TITLE, WHITE),
+            );
+            return;
+        }
+    };
+    draw_centered_text(
+        &table.title,
+        SCREEN_W / 2.,
+        y_start,
+        get_text_params(&resources, FONT_SIZE_TITLE, WHITE),
+    );
+    for (i, entry) in table.entries.iter().enumerate() {
+        draw_centered_text(
+            &format!("{: >2} {} {: >5}", i + 1, entry.initials, entry.score),
+            SCREEN_W / 2.,
+            y_start + FONT_SIZE_TITLE + (i + 1) as f32 * FONT_SIZE_SCORE,
+            get_text_params(&resources, FONT_SIZE_SCORE, WHITE),
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+const OUTLINE_SEGMENTS: usize = 32;
+
+/// Outline of a hole around its position, scaled by the hole radius
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum HoleShape {
+    #[default]
+    Circle,
+    /// Half axes of `radius` and `radius * aspect`, rotated by `angle` (rad)
+    Ellipse { aspect: f32, angle: f32 },
+    /// Segment `length` radii long with round ends, rotated by `angle` (rad)
+    Capsule { length: f32, angle: f32 },
+    /// Corners in radii from the hole position, which has to see all of them
+    Polygon { points: Vec<Vec2> },
+}
+
+impl HoleShape {
+    pub fn is_circle(&self) -> bool {
+        *self == HoleShape::Circle
+    }
+
+    /// Whether the parameters describe a shape with an inside
+    pub fn is_valid(&self) -> bool {
+        match self {
+            HoleShape::Circle => true,
+            HoleShape::Ellipse { aspect, .. } => *aspect > 0.,
+            HoleShape::Capsule { length, .. } => *length >= 0.,
+            HoleShape::Polygon { points } => points.len() >= 3,
+        }
+    }
+
+    /// Whether `p`, relative to the hole position, is inside the hole
+    pub fn contains(&self, p: Vec2, radius: f32) -> bool {
+        if radius <= 0. {
+            return false;
+        }
+        match self {
+            HoleShape::Circle => p.length() < radius,
+            HoleShape::Ellipse { aspect, angle } => {
+                let p = rotate(p, -angle);
+                let (a, b) = (radius, radius * aspect);
+                (p.x / a).powi(2) + (p.y / b).powi(2) < 1.
+            }
+            HoleShape::Capsule { .. } => {
+                let (a, b) = self.capsule_ends(radius);
+                p.distance(nearest_on_segment(p, a, b)) < radius
+            }
+            HoleShape::Polygon { .. } => {
+                // Even-odd rule: count edges crossed by a ray towards +x
+                let corners = self.corners(radius);
+                let mut inside = false;
+                for (a, b) in edges(&corners) {
+                    if (a.y > p.y) != (b.y > p.y)
+                        && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Point on the rim nearest to `p` and the outward normal there,
+    /// relative to the hole position
+    pub fn rim(&self, p: Vec2, radius: f32) -> (Vec2, Vec2) {
+        match self {
+            HoleShape::Circle => {
+                let normal = direction(p, Vec2::new(1.0, 0.0));
+                (normal * radius, normal)
+            }
+            HoleShape::Ellipse { aspect, angle } => {
+                let (a, b) = (radius, radius * aspect);
+                let edge = nearest_on_ellipse(rotate(p, -angle), a, b);
+                // The gradient of the implicit equation is normal to the ellipse
+                let gradient = Vec2::new(edge.x / (a * a), edge.y / (b * b));
+                let normal = direction(gradient, Vec2::new(1.0, 0.0));
+                (rotate(edge, *angle), rotate(normal, *angle))
+            }
+            HoleShape::Capsule { angle, .. } => {
+                let (a, b) = self.capsule_ends(radius);
+                let nearest = nearest_on_segment(p, a, b);
+                let normal = direction(p - nearest, rotate(Vec2::new(0.0, 1.0), *angle));
+                (nearest + normal * radius, normal)
+            }
+            HoleShape::Polygon { .. } => {
+                let corners = self.corners(radius);
+                let mut rim = (Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0));
+                let mut min_distance = f32::INFINITY;
+                for (a, b) in edges(&corners) {
+                    let nearest = nearest_on_segment(p, a, b);
+                    if p.distance(nearest) < min_distance {
+                        min_distance = p.distance(nearest);
+                        let side = b - a;
+                        let mut normal = direction(Vec2::new(side.y, -side.x), Vec2::new(1.0, 0.0));
+                        // Corners are seen from the hole position, so the rim faces away from it
+                        if normal.dot(a + b) < 0. {
+                            normal = -normal;
+                        }
+                        rim = (nearest, normal);
+                    }
+                }
+                rim
+            }
+        }
+    }
+
+    /// Points around the rim relative to the hole position, for drawing and overlap tests
+    pub fn outline(&self, radius: f32) -> Vec<Vec2> {
+        let arc = |center: Vec2, from: f32, segments: usize| {
+            (0..=segments).map(move |i| {
+                let t = from + i as f32 / segments as f32 * PI;
+                center + Vec2::new(t.cos(), t.sin()) * radius
+            })
+        };
+        match self {
+            HoleShape::Circle | HoleShape::Ellipse { .. } => {
+                let (aspect, angle) = match self {
+                    HoleShape::Ellipse { aspect, angle } => (*aspect, *angle),
+                    _ => (1., 0.),
+                };
+                (0..OUTLINE_SEGMENTS)
+                    .map(|i| {
+                        let t = i as f32 / OUTLINE_SEGMENTS as f32 * 2. * PI;
+                        rotate(Vec2::new(t.cos(), t.sin() * aspect) * radius, angle)
+                    })
+                    .collect()
+            }
+            HoleShape::Capsule { angle, .. } => {
+                let (a, b) = self.capsule_ends(radius);
+                let half = OUTLINE_SEGMENTS / 2;
+                arc(b, angle - PI / 2., half)
+                    .chain(arc(a, angle + PI / 2., half))
+                    .collect()
+            }
+            HoleShape::Polygon { .. } => self.corners(radius),
+        }
+    }
+
+    pub fn rotated(&self, delta: f32) -> HoleShape {
+        match self {
+            HoleShape::Circle => HoleShape::Circle,
+            HoleShape::Ellipse { aspect, angle } => HoleShape::Ellipse {
+                aspect: *aspect,
+                angle: angle + delta,
+            },
+            HoleShape::Capsule { length, angle } => HoleShape::Capsule {
+                length: *length,
+                angle: angle + delta,
+            },
+            HoleShape::Polygon { points } => HoleShape::Polygon {
+                points: points.iter().map(|p| rotate(*p, delta)).collect(),
+            },
+        }
+    }
+
+    fn capsule_ends(&self, radius: f32) -> (Vec2, Vec2) {
+        match self {
+            HoleShape::Capsule { length, angle } => {
+                let half = rotate(Vec2::new(length * radius / 2., 0.0), *angle);
+                (-half, half)
+            }
+            _ => (Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0)),
+        }
+    }
+
+    fn corners(&self, radius: f32) -> Vec<Vec2> {
+        match self {
+            HoleShape::Polygon { points } => points.iter().map(|p| *p * radius).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Sides of a closed polygon
+fn edges(corners: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    corners
+        .iter()
+        .zip(corners.iter().cycle().skip(1))
+        .map(|(a, b)| (*a, *b))
+}
+
+fn direction(v: Vec2, fallback: Vec2) -> Vec2 {
+    match v.length() {
+        length if length > f32::EPSILON => v / length,
+        _ => fallback,
+    }
+}
+
+fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(cos * v.x - sin * v.y, sin * v.x + cos * v.y)
+}
+
+fn nearest_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let line = b - a;
+    match line.length_squared() {
+        l if l > 0. => a + line * ((p - a).dot(line) / l).clamp(0., 1.),
+        _ => a,
+    }
+}
+
+/// Nearest point to `p` on an axis aligned ellipse with half axes `a` and `b`,
+/// refined by a few fixed point iterations on the curvature center
+fn nearest_on_ellipse(p: Vec2, a: f32, b: f32) -> Vec2 {
+    let (px, py) = (p.x.abs(), p.y.abs());
+    let (mut tx, mut ty) = (
+        std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+    );
+    for _ in 0..3 {
+        let (x, y) = (a * tx, b * ty);
+        let ex = (a * a - b * b) * tx.powi(3) / a;
+        let ey = (b * b - a * a) * ty.powi(3) / b;
+        let r = Vec2::new(x - ex, y - ey).length();
+        let q = Vec2::new(px - ex, py - ey).length().max(f32::EPSILON);
+        tx = ((px - ex) * r / q + ex) / a;
+        ty = ((py - ey) * r / q + ey) / b;
+        tx = tx.clamp(0., 1.);
+        ty = ty.clamp(0., 1.);
+        let t = Vec2::new(tx, ty).length().max(f32::EPSILON);
+        tx /= t;
+        ty /= t;
+    }
+    Vec2::new((a * tx).copysign(p.x), (b * ty).copysign(p.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn square() -> HoleShape {
+        HoleShape::Polygon {
+            points: vec![
+                Vec2::new(-1., -1.),
+                Vec2::new(1., -1.),
+                Vec2::new(1., 1.),
+                Vec2::new(-1., 1.),
+            ],
+        }
+    }
+
+    #[test]
+    fn contains_inside_only() {
+        let ellipse = HoleShape::Ellipse {
+            aspect: 0.5,
+            angle: PI / 2.,
+        };
+        assert!(ellipse.contains(Vec2::new(0., 0.9), 1.));
+        assert!(!ellipse.contains(Vec2::new(0.9, 0.), 1.));
+
+        let capsule = HoleShape::Capsule {
+            length: 2.,
+            angle: 0.,
+        };
+        assert!(capsule.contains(Vec2::new(1.9, 0.), 1.));
+        assert!(capsule.contains(Vec2::new(1., 0.9), 1.));
+        assert!(!capsule.contains(Vec2::new(0., 1.1), 1.));
+
+        assert!(square().contains(Vec2::new(0.9, -0.9), 1.));
+        assert!(!square().contains(Vec2::new(1.1, 0.), 1.));
+
+        // A closed hole contains nothing
+        assert!(!HoleShape::Circle.contains(Vec2::new(0., 0.), 0.));
+    }
+
+    #[test]
+    fn rim_is_nearest_edge_with_outward_normal() {
+        let (edge, normal) = HoleShape::Circle.rim(Vec2::new(0., 0.5), 2.);
+        assert_near(edge, Vec2::new(0., 2.));
+        assert_near(normal, Vec2::new(0., 1.));
+
+        let ellipse = HoleShape::Ellipse {
+            aspect: 0.5,
+            angle: 0.,
+        };
+        let (edge, normal) = ellipse.rim(Vec2::new(0., -0.2), 1.);
+        assert_near(edge, Vec2::new(0., -0.5));
+        assert_near(normal, Vec2::new(0., -1.));
+
+        let capsule = HoleShape::Capsule {
+            length: 2.,
+            angle: 0.,
+        };
+        let (edge, normal) = capsule.rim(Vec2::new(0.5, 0.5), 1.);
+        assert_near(edge, Vec2::new(0.5, 1.));
+        assert_near(normal, Vec2::new(0., 1.));
+
+        let (edge, normal) = square().rim(Vec2::new(0.8, 0.2), 1.);
+        assert_near(edge, Vec2::new(1., 0.2));
+        assert_near(normal, Vec2::new(1., 0.));
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(HoleShape::Circle.is_valid());
+        assert!(!HoleShape::Ellipse {
+            aspect: 0.,
+            angle: 0.
+        }
+        .is_valid());
+        assert!(!HoleShape::Capsule {
+            length: -1.,
+            angle: 0.
+        }
+        .is_valid());
+        assert!(!HoleShape::Polygon {
+            points: vec![Vec2::new(1., 0.), Vec2::new(0., 1.)]
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,152 @@
+use super::balls;
+use super::help;
+use super::level;
+use super::rod;
+use crate::{
+    debug::DebugChannel,
+    input::{Input, InputSource},
+    state::{Event, GameState},
+    utils::return_if_some,
+};
+use std::mem;
+
+/// Advance the simulation by `dt` seconds.
+///
+/// Does not touch window, input or time globals: game time is advanced only
+/// by `dt`, so the same inputs always produce the same trajectory and events.
+pub fn step(
+    game: &mut GameState,
+    input: &Input,
+    dt: f32,
+    debug: &mut DebugChannel,
+) -> Option<Event> {
+    debug.next_frame();
+    game.progress.elapsed += dt as f64;
+    help::update_help(game, input);
+    rod::update_actuators(&mut game.objects.actuators, input, &game.physics, dt);
+    rod::update_rod_physics(
+        &mut game.objects.balls,
+        &game.objects.actuators,
+        &game.physics,
+        debug,
+    );
+    balls::update_ball_collisions(&mut game.objects.balls, &game.physics);
+    return_if_some!(level::update_level(game, debug));
+    balls::update_balls(&mut game.objects.balls, &game.physics, dt);
+    None
+}
+
+/// Apply the round transition for `event`, as `State::transition` does in game
+pub fn apply_event(game: GameState, event: &Event) -> GameState {
+    match event {
+        Event::RoundLost | Event::GameEnded => game.reset_round(),
+        Event::RoundCompleted => game.next_round(),
+        Event::GameCompleted => game.next_round().reset_round(),
+        _ => game,
+    }
+}
+
+/// Headless runner that applies round transitions itself,
+/// for running rounds without a window (tests, batch tools).
+pub struct Simulation {
+    pub game: GameState,
+    pub debug: DebugChannel,
+    pub finished: bool,
+}
+
+impl Simulation {
+    pub fn new(game: GameState) -> Self {
+        Simulation {
+            game: game.reset_round(),
+            debug: DebugChannel::new(),
+            finished: false,
+        }
+    }
+
+    pub fn step(&mut self, input: &Input, dt: f32) -> Option<Event> {
+        if self.finished {
+            return None;
+        }
+        let event = step(&mut self.game, input, dt, &mut self.debug);
+        if let Some(ev) = &event {
+            self.finished = matches!(ev, Event::GameCompleted | Event::GameEnded);
+            self.game = apply_event(mem::take(&mut self.game), ev);
+        }
+        event
+    }
+
+    /// Step with inputs from `controller` until the game finishes or
+    /// `max_steps` is reached, returning all emitted events.
+    pub fn run(
+        &mut self,
+        controller: &mut dyn InputSource,
+        dt: f32,
+        max_steps: usize,
+    ) -> Vec<Event> {
+        let mut events = vec![];
+        for _ in 0..max_steps {
+            if self.finished {
+                break;
+            }
+            let input = controller.next_input(&self.game);
+            events.extend(self.step(&input, dt));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestep::FIXED_DELTATIME;
+
+    // Raises the rod while tilting it back and forth
+    fn sweep(i: usize) -> Input {
+        let tilt = (i as f32 * FIXED_DELTATIME * 1.3).sin();
+        Input {
+            actuators: [1.0, 1.0 - tilt],
+            ..Default::default()
+        }
+    }
+
+    fn example() -> GameState {
+        GameState::load("level_example.json").unwrap()
+    }
+
+    #[test]
+    fn step_is_deterministic() {
+        let mut first = Simulation::new(example());
+        let mut second = Simulation::new(example());
+        for i in 0..5_000 {
+            let input = sweep(i);
+            assert_eq!(
+                first.step(&input, FIXED_DELTATIME),
+                second.step(&input, FIXED_DELTATIME)
+            );
+            assert_eq!(first.game, second.game, "diverged at step {}", i);
+        }
+    }
+
+    #[test]
+    fn run_plays_many_rounds() {
+        let mut rounds = 0;
+        for seed in 0..200 {
+            let mut game = example();
+            game.progress.balls_left = 10;
+            let mut sim = Simulation::new(game);
+            let mut i = seed * 97;
+            let mut controller = |_: &GameState| {
+                i += 1;
+                sweep(i)
+            };
+            let events = sim.run(&mut controller, FIXED_DELTATIME, 100_000);
+            assert!(sim.finished, "game {} did not finish", seed);
+            assert!(matches!(
+                events.last(),
+                Some(Event::GameEnded | Event::GameCompleted)
+            ));
+            rounds += events.len();
+        }
+        assert!(rounds >= 2_000, "only {} rounds played", rounds);
+    }
+}
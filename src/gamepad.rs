@@ -0,0 +1,127 @@
+#[cfg(not(target_arch = "wasm32"))]
+use gilrs::{Axis, Button, Event as GamepadEvent, EventType, GamepadId, Gilrs};
+
+// Stick travel ignored around the center, as a fraction of full travel
+const DEADZONE: f32 = 0.15;
+// Exponent of the response curve, above 1 gives finer control near the center
+const RESPONSE_EXPONENT: f32 = 1.6;
+
+/// Gamepad input for one frame, turned into `Input` by `GamepadSource`
+#[derive(Debug, Default)]
+pub struct GamepadInput {
+    pub actuators: [f32; 2],
+    pub menu_up: bool,
+    pub menu_down: bool,
+    pub enter: bool,
+    pub escape: bool,
+}
+
+/// Connected gamepads, the most recently connected one is used
+pub struct Gamepads {
+    #[cfg(not(target_arch = "wasm32"))]
+    gilrs: Option<Gilrs>,
+    #[cfg(not(target_arch = "wasm32"))]
+    active: Option<GamepadId>,
+}
+
+impl Gamepads {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new() -> Self {
+        let gilrs = Gilrs::new()
+            .map_err(|err| eprintln!("Gamepads not available: {}", err))
+            .ok();
+        let active = gilrs
+            .as_ref()
+            .and_then(|gilrs| gilrs.gamepads().map(|(id, _)| id).last());
+        Gamepads { gilrs, active }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn new() -> Self {
+        Gamepads {}
+    }
+
+    /// Handle connects and button presses since the last call and read the sticks
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> GamepadInput {
+        let mut input = GamepadInput::default();
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return input,
+        };
+        while let Some(GamepadEvent { id, event, .. }) = gilrs.next_event() {
+            match event {
+                EventType::Connected => self.active = Some(id),
+                EventType::Disconnected if self.active == Some(id) => {
+                    self.active = gilrs
+                        .gamepads()
+                        .map(|(id, _)| id)
+                        .filter(|other| *other != id)
+                        .last();
+                }
+                EventType::ButtonPressed(button, _) if self.active == Some(id) => match button {
+                    Button::DPadUp => input.menu_up = true,
+                    Button::DPadDown => input.menu_down = true,
+                    Button::South | Button::Start => input.enter = true,
+                    Button::Select => input.escape = true,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if let Some(gamepad) = self.active.map(|id| gilrs.gamepad(id)) {
+            // Sticks drive the actuators; analog triggers raise them and bumpers lower them
+            let sides = [
+                (Axis::LeftStickY, Button::LeftTrigger2, Button::LeftTrigger),
+                (
+                    Axis::RightStickY,
+                    Button::RightTrigger2,
+                    Button::RightTrigger,
+                ),
+            ];
+            for (actuator, (stick, trigger, bumper)) in input.actuators.iter_mut().zip(sides) {
+                let stick = shape_axis(gamepad.value(stick));
+                let trigger = shape_axis(gamepad.button_data(trigger).map_or(0., |b| b.value()));
+                let bumper = gamepad.is_pressed(bumper) as i32 as f32;
+                *actuator = match stick {
+                    s if s != 0. => s,
+                    _ => trigger - bumper,
+                };
+            }
+        }
+        input
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn poll(&mut self) -> GamepadInput {
+        GamepadInput::default()
+    }
+}
+
+/// Remove the deadzone, rescale the remaining travel to 0..1 and apply the response curve
+pub fn shape_axis(value: f32) -> f32 {
+    let travel = ((value.abs() - DEADZONE) / (1. - DEADZONE)).clamp(0., 1.);
+    match travel {
+        t if t > 0. => t.powf(RESPONSE_EXPONENT).copysign(value),
+        _ => 0.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shape_axis_deadzone_and_range() {
+        assert_eq!(shape_axis(0.), 0.);
+        assert_eq!(shape_axis(DEADZONE), 0.);
+        assert_eq!(shape_axis(-DEADZONE), 0.);
+        assert_eq!(shape_axis(1.), 1.);
+        assert_eq!(shape_axis(-1.), -1.);
+        assert_eq!(shape_axis(2.), 1.);
+        // Small deflections past the deadzone move the actuator slowly
+        let half = shape_axis(0.5);
+        assert!(half > 0. && half < 0.5);
+        assert_eq!(shape_axis(-0.5), -half);
+    }
+}
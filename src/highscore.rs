@@ -0,0 +1,208 @@
+use crate::{
+    campaign::{Campaign, CAMPAIGN_FILE},
+    config,
+    state::GameState,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs::{self, File},
+    path::PathBuf,
+};
+
+const HIGHSCORE_FILE: &str = "highscores.json";
+pub const TABLE_SIZE: usize = 10;
+
+/// High score tables keyed by level file or campaign,
+/// persisted in the user data directory
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HighScores {
+    pub tables: BTreeMap<String, HighScoreTable>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HighScoreTable {
+    pub title: String,
+    pub entries: Vec<HighScore>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub initials: String,
+    pub score: u16,
+}
+
+/// A score waiting for initials, and the table it goes to
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingScore {
+    pub key: String,
+    pub title: String,
+    pub score: u16,
+}
+
+impl HighScores {
+    /// Load saved high scores, starting empty if there are none
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or("No user data directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer(&file, self)?;
+        Ok(())
+    }
+
+    pub fn qualifies(&self, key: &str, score: u16) -> bool {
+        if score == 0 {
+            return false;
+        }
+        match self.tables.get(key) {
+            Some(table) if table.entries.len() >= TABLE_SIZE => {
+                table.entries.iter().any(|entry| entry.score < score)
+            }
+            _ => true,
+        }
+    }
+
+    pub fn insert(&mut self, pending: &PendingScore, initials: &str) {
+        let table = self
+            .tables
+            .entry(pending.key.to_string())
+            .or_insert_with(|| HighScoreTable {
+                title: pending.title.to_string(),
+                entries: vec![],
+            });
+        table.entries.push(HighScore {
+            initials: initials.to_string(),
+            score: pending.score,
+        });
+        table.entries.sort_by_key(|entry| Reverse(entry.score));
+        table.entries.truncate(TABLE_SIZE);
+    }
+
+    fn path() -> Option<PathBuf> {
+        config::user_data_dir().map(|dir| dir.join(HIGHSCORE_FILE))
+    }
+}
+
+/// Scores of a finished game that make it into a table: one per completed
+/// level, plus the total if the campaign was played from its first level
+pub fn pending_scores(game: &GameState, scores: &HighScores) -> Vec<PendingScore> {
+    let results = &game.progress.results;
+    let mut pending: Vec<PendingScore> = results
+        .iter()
+        .map(|result| PendingScore {
+            key: result.level_file.to_string(),
+            title: result.name.to_string(),
+            score: result.score,
+        })
+        .collect();
+    if let Ok(campaign) = Campaign::load(CAMPAIGN_FILE) {
+        // Either finished, or lost on the level after the last completed one
+        let from_start = results
+            .iter()
+            .zip(campaign.levels.iter())
+            .all(|(result, level)| result.level_file == level.level_file)
+            && (results.len() == campaign.levels.len()
+                || game.progress.campaign_level == Some(results.len()));
+        if from_start {
+            pending.push(PendingScore {
+                key: format!("campaign:{}", campaign.name),
+                title: campaign.name.to_string(),
+                score: game.progress.score,
+            });
+        }
+    }
+    pending
+        .into_iter()
+        .filter(|pending| scores.qualifies(&pending.key, pending.score))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::LevelResult;
+
+    fn pending_score(key: &str, score: u16) -> PendingScore {
+        PendingScore {
+            key: key.to_string(),
+            title: key.to_string(),
+            score,
+        }
+    }
+
+    fn full_table(key: &str) -> HighScores {
+        let mut scores = HighScores::default();
+        for score in 1..=TABLE_SIZE as u16 {
+            scores.insert(&pending_score(key, score * 100), "AAA");
+        }
+        scores
+    }
+
+    fn result(level_file: &str, score: u16) -> LevelResult {
+        LevelResult {
+            level_file: level_file.to_string(),
+            name: level_file.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn qualifies_beats_lowest_of_full_table() {
+        let empty = HighScores::default();
+        assert!(empty.qualifies("level_a.json", 1));
+        assert!(!empty.qualifies("level_a.json", 0));
+
+        let scores = full_table("level_a.json");
+        assert!(scores.qualifies("level_a.json", 101));
+        assert!(!scores.qualifies("level_a.json", 100));
+        assert!(scores.qualifies("level_b.json", 50));
+    }
+
+    #[test]
+    fn insert_orders_and_truncates() {
+        let mut scores = full_table("level_a.json");
+        scores.insert(&pending_score("level_a.json", 550), "BOB");
+        let entries = &scores.tables["level_a.json"].entries;
+        assert_eq!(entries.len(), TABLE_SIZE);
+        assert!(entries.windows(2).all(|w| w[0].score >= w[1].score));
+        assert_eq!(entries[5].initials, "BOB");
+        assert_eq!(entries.last().map(|entry| entry.score), Some(200));
+    }
+
+    #[test]
+    fn pending_scores_include_campaign_total_from_start() {
+        let mut game = GameState::new();
+        game.progress.results = vec![result("level_example.json", 100)];
+        game.progress.campaign_level = Some(1);
+        game.progress.score = 340;
+        let campaign = Campaign::load(CAMPAIGN_FILE).unwrap();
+        let campaign_key = format!("campaign:{}", campaign.name);
+
+        let pending = pending_scores(&game, &HighScores::default());
+        let keys: Vec<&str> = pending.iter().map(|pending| pending.key.as_str()).collect();
+        assert_eq!(keys, ["level_example.json", campaign_key.as_str()]);
+        assert_eq!(pending[1].score, 340);
+
+        // Scores that do not beat a full table are dropped
+        let pending = pending_scores(&game, &full_table("level_example.json"));
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].key, campaign_key);
+
+        // Started from the level select, not the first level
+        game.progress.results = vec![result("level_zigzag.json", 300)];
+        game.progress.campaign_level = Some(1);
+        let pending = pending_scores(&game, &HighScores::default());
+        assert_eq!(pending, vec![pending_score("level_zigzag.json", 300)]);
+    }
+}
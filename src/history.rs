@@ -0,0 +1,244 @@
+use crate::{
+    game::shapes::HoleShape,
+    state::{GameLevelState, Hole, Obstacle},
+};
+use macroquad::math::Vec2;
+
+/// Reversible edit of a level
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditorCommand {
+    AddHole(Hole),
+    RemoveHole {
+        index: usize,
+        hole: Hole,
+        goals: Vec<usize>,
+    },
+    MoveHole {
+        index: usize,
+        from: Vec2,
+        to: Vec2,
+    },
+    ResizeHole {
+        index: usize,
+        from: f32,
+        to: f32,
+    },
+    SetHoleShape {
+        index: usize,
+        from: HoleShape,
+        to: HoleShape,
+    },
+    SetGoals {
+        from: Vec<usize>,
+        to: Vec<usize>,
+    },
+    SetBackground {
+        from: String,
+        to: String,
+    },
+    AddObstacle(Obstacle),
+    RemoveObstacle {
+        index: usize,
+        obstacle: Obstacle,
+    },
+    ReplaceObstacle {
+        index: usize,
+        from: Obstacle,
+        to: Obstacle,
+    },
+}
+
+/// Undo and redo stacks of editor commands
+#[derive(Debug, Default, PartialEq)]
+pub struct History {
+    done: Vec<EditorCommand>,
+    undone: Vec<EditorCommand>,
+}
+
+impl EditorCommand {
+    pub fn apply(&self, level: &mut GameLevelState) {
+        match self {
+            EditorCommand::AddHole(hole) => level.holes.push(hole.clone()),
+            EditorCommand::RemoveHole { index, .. } => {
+                level.remove_hole(*index);
+            }
+            EditorCommand::MoveHole { index, to, .. } => level.holes[*index].pos = *to,
+            EditorCommand::ResizeHole { index, to, .. } => level.holes[*index].radius = *to,
+            EditorCommand::SetHoleShape { index, to, .. } => level.holes[*index].shape = to.clone(),
+            EditorCommand::SetGoals { to, .. } => level.goals = to.clone(),
+            EditorCommand::SetBackground { to, .. } => level.background_image = to.to_string(),
+            EditorCommand::AddObstacle(obstacle) => level.obstacles.push(obstacle.clone()),
+            EditorCommand::RemoveObstacle { index, .. } => {
+                level.obstacles.remove(*index);
+            }
+            EditorCommand::ReplaceObstacle { index, to, .. } => {
+                level.obstacles[*index] = to.clone()
+            }
+        }
+    }
+
+    pub fn revert(&self, level: &mut GameLevelState) {
+        match self {
+            EditorCommand::AddHole(_) => {
+                level.holes.pop();
+            }
+            EditorCommand::RemoveHole { index, hole, goals } => {
+                level.holes.insert(*index, hole.clone());
+                level.goals = goals.clone();
+            }
+            EditorCommand::MoveHole { index, from, .. } => level.holes[*index].pos = *from,
+            EditorCommand::ResizeHole { index, from, .. } => level.holes[*index].radius = *from,
+            EditorCommand::SetHoleShape { index, from, .. } => {
+                level.holes[*index].shape = from.clone()
+            }
+            EditorCommand::SetGoals { from, .. } => level.goals = from.clone(),
+            EditorCommand::SetBackground { from, .. } => level.background_image = from.to_string(),
+            EditorCommand::AddObstacle(_) => {
+                level.obstacles.pop();
+            }
+            EditorCommand::RemoveObstacle { index, obstacle } => {
+                level.obstacles.insert(*index, obstacle.clone())
+            }
+            EditorCommand::ReplaceObstacle { index, from, .. } => {
+                level.obstacles[*index] = from.clone()
+            }
+        }
+    }
+}
+
+impl History {
+    /// Apply a command and record it
+    pub fn execute(&mut self, command: EditorCommand, level: &mut GameLevelState) {
+        command.apply(level);
+        self.push(command);
+    }
+
+    /// Record a command that has already been applied, e.g. at the end of a drag
+    pub fn push(&mut self, command: EditorCommand) {
+        self.undone.clear();
+        // Successive wheel steps on the same hole are undone as one
+        if let (
+            Some(EditorCommand::ResizeHole { index, to, .. }),
+            EditorCommand::ResizeHole {
+                index: next_index,
+                to: next_to,
+                ..
+            },
+        ) = (self.done.last_mut(), &command)
+        {
+            if index == next_index {
+                *to = *next_to;
+                return;
+            }
+        }
+        self.done.push(command);
+    }
+
+    pub fn undo(&mut self, level: &mut GameLevelState) -> bool {
+        match self.done.pop() {
+            Some(command) => {
+                command.revert(level);
+                self.undone.push(command);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, level: &mut GameLevelState) -> bool {
+        match self.undone.pop() {
+            Some(command) => {
+                command.apply(level);
+                self.done.push(command);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hole(x: f32) -> Hole {
+        Hole {
+            pos: Vec2::new(x, 1.0),
+            radius: 0.05,
+            ..Default::default()
+        }
+    }
+
+    fn resize(index: usize, from: f32, to: f32) -> EditorCommand {
+        EditorCommand::ResizeHole { index, from, to }
+    }
+
+    #[test]
+    fn undo_and_redo_restore_level() {
+        let mut level = GameLevelState::new();
+        let mut history = History::default();
+        history.execute(EditorCommand::AddHole(hole(0.2)), &mut level);
+        history.execute(EditorCommand::AddHole(hole(0.6)), &mut level);
+        let goals = EditorCommand::SetGoals {
+            from: vec![],
+            to: vec![1, 0],
+        };
+        history.execute(goals, &mut level);
+        let before_remove = level.holes.clone();
+        let remove = EditorCommand::RemoveHole {
+            index: 0,
+            hole: level.holes[0].clone(),
+            goals: level.goals.clone(),
+        };
+        history.execute(remove, &mut level);
+        assert_eq!(level.holes, vec![hole(0.6)]);
+        assert_eq!(level.goals, vec![0]);
+
+        assert!(history.undo(&mut level));
+        assert_eq!(level.holes, before_remove);
+        assert_eq!(level.goals, vec![1, 0]);
+
+        while history.undo(&mut level) {}
+        assert!(level.holes.is_empty());
+        assert!(level.goals.is_empty());
+
+        while history.redo(&mut level) {}
+        assert_eq!(level.holes, vec![hole(0.6)]);
+        assert_eq!(level.goals, vec![0]);
+        assert!(!history.redo(&mut level));
+    }
+
+    #[test]
+    fn new_command_clears_redo() {
+        let mut level = GameLevelState::new();
+        let mut history = History::default();
+        history.execute(EditorCommand::AddHole(hole(0.2)), &mut level);
+        history.undo(&mut level);
+        history.execute(EditorCommand::AddHole(hole(0.6)), &mut level);
+        assert!(!history.redo(&mut level));
+        assert_eq!(level.holes, vec![hole(0.6)]);
+    }
+
+    #[test]
+    fn successive_resizes_merge() {
+        let mut level = GameLevelState::new();
+        let mut history = History::default();
+        history.execute(EditorCommand::AddHole(hole(0.2)), &mut level);
+        history.execute(EditorCommand::AddHole(hole(0.6)), &mut level);
+        history.execute(resize(0, 0.05, 0.06), &mut level);
+        history.execute(resize(0, 0.06, 0.07), &mut level);
+        history.execute(resize(1, 0.05, 0.04), &mut level);
+        assert_eq!(level.holes[0].radius, 0.07);
+
+        // The resize of the other hole is separate, both of hole 0 are one step
+        history.undo(&mut level);
+        assert_eq!(level.holes[1].radius, 0.05);
+        assert_eq!(level.holes[0].radius, 0.07);
+        history.undo(&mut level);
+        assert_eq!(level.holes[0].radius, 0.05);
+        assert_eq!(level.holes.len(), 2);
+
+        history.redo(&mut level);
+        assert_eq!(level.holes[0].radius, 0.07);
+    }
+}
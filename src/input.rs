@@ -1,5 +1,18 @@
-use macroquad::prelude::*;
-#[derive(Debug, PartialEq)]
+use crate::{
+    bindings::{Action, KeyBindings},
+    game::{autopilot::Autopilot, game::game_camera},
+    gamepad::Gamepads,
+    replay::{Replay, ReplayFrame},
+    settings::Settings,
+    state::{Event, GameState},
+    touch::TouchSliders,
+    Result,
+};
+use macroquad::experimental::collections::storage;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Input {
     pub actuators: [f32; 2],
     pub menu_up: bool,
@@ -8,19 +21,345 @@ pub struct Input {
     pub escape: bool,
 }
 
-fn read_updown(up: KeyCode, down: KeyCode) -> f32 {
-    is_key_down(up) as i32 as f32 - is_key_down(down) as i32 as f32
+/// Anything that can drive the game: a person, a recording or a bot
+pub trait InputSource {
+    /// Input for the next step of `game`
+    fn next_input(&mut self, game: &GameState) -> Input;
+
+    /// Whether a person uses this source, and can navigate the menus with it
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    /// Called after the game moved on because of `event`
+    fn on_event(&mut self, _event: &Event) {}
+}
+
+/// Scripted controllers are plain closures
+impl<F: FnMut(&GameState) -> Input> InputSource for F {
+    fn next_input(&mut self, game: &GameState) -> Input {
+        self(game)
+    }
+}
+
+/// Keyboard, touch sliders and gamepad together
+pub struct HumanSource;
+
+/// Keys, as bound in the settings
+pub struct KeyboardSource;
+
+pub struct GamepadSource;
+
+/// Inputs of the steps of a recorded session, kept in line with the game at
+/// the recorded transitions, then no input
+pub struct ReplaySource {
+    frames: VecDeque<ReplayFrame>,
+}
+
+impl InputSource for HumanSource {
+    fn next_input(&mut self, game: &GameState) -> Input {
+        let keys = KeyboardSource.next_input(game);
+        let gamepad = GamepadSource.next_input(game);
+        let touch = storage::get_mut::<TouchSliders>().update(&game_camera(game));
+        Input {
+            actuators: [
+                key_or_analog(keys.actuators[0], touch[0].unwrap_or(gamepad.actuators[0])),
+                key_or_analog(keys.actuators[1], touch[1].unwrap_or(gamepad.actuators[1])),
+            ],
+            menu_up: keys.menu_up || gamepad.menu_up,
+            menu_down: keys.menu_down || gamepad.menu_down,
+            enter: keys.enter || gamepad.enter,
+            escape: keys.escape || gamepad.escape,
+        }
+    }
+
+    fn is_human(&self) -> bool {
+        true
+    }
+}
+
+impl InputSource for KeyboardSource {
+    fn next_input(&mut self, _game: &GameState) -> Input {
+        let settings = storage::get::<Settings>();
+        let bindings = &settings.bindings;
+        Input {
+            actuators: [
+                read_updown(bindings, Action::LeftUp, Action::LeftDown),
+                read_updown(bindings, Action::RightUp, Action::RightDown),
+            ],
+            menu_up: bindings.is_pressed(Action::MenuUp),
+            menu_down: bindings.is_pressed(Action::MenuDown),
+            enter: bindings.is_pressed(Action::Enter),
+            escape: bindings.is_pressed(Action::Escape),
+        }
+    }
+
+    fn is_human(&self) -> bool {
+        true
+    }
+}
+
+impl InputSource for GamepadSource {
+    fn next_input(&mut self, _game: &GameState) -> Input {
+        let gamepad = storage::get_mut::<Gamepads>().poll();
+        Input {
+            actuators: gamepad.actuators,
+            menu_up: gamepad.menu_up,
+            menu_down: gamepad.menu_down,
+            enter: gamepad.enter,
+            escape: gamepad.escape,
+        }
+    }
+
+    fn is_human(&self) -> bool {
+        true
+    }
+}
+
+impl ReplaySource {
+    pub fn new(replay: Replay) -> Self {
+        ReplaySource {
+            frames: replay.frames.into(),
+        }
+    }
+
+    pub fn load(file_name: &str) -> Result<Self> {
+        Ok(ReplaySource::new(Replay::load(file_name)?))
+    }
+}
+
+impl InputSource for ReplaySource {
+    fn next_input(&mut self, _game: &GameState) -> Input {
+        match self.frames.front() {
+            Some(ReplayFrame::Step { input, .. }) => {
+                let input = input.clone();
+                self.frames.pop_front();
+                input
+            }
+            // Hold still until the game reaches the recorded transition
+            _ => Input::default(),
+        }
+    }
+
+    fn on_event(&mut self, event: &Event) {
+        // Steps recorded after the game got there, e.g. while the screen
+        // faded out for longer, are dropped with the transition
+        let next = self
+            .frames
+            .iter()
+            .position(|frame| frame.transition().is_some());
+        if let Some(next) = next.filter(|next| self.frames[*next].transition() == Some(event)) {
+            self.frames.drain(..=next);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Both,
+    Left,
+    Right,
 }
 
-pub fn update_input() -> Input {
-    Input {
-        actuators: [
-            read_updown(KeyCode::W, KeyCode::S),
-            read_updown(KeyCode::Up, KeyCode::Down),
-        ],
-        menu_up: is_key_pressed(KeyCode::Up),
-        menu_down: is_key_pressed(KeyCode::Down),
-        enter: is_key_pressed(KeyCode::Enter),
-        escape: is_key_pressed(KeyCode::Escape),
+impl Side {
+    fn controls(&self, actuator: usize) -> bool {
+        match self {
+            Side::Both => true,
+            Side::Left => actuator == 0,
+            Side::Right => actuator == 1,
+        }
+    }
+}
+
+pub struct Player {
+    pub source: Box<dyn InputSource>,
+    pub side: Side,
+}
+
+/// Input sources of the players, each driving one or both actuators
+pub struct Players {
+    players: Vec<Player>,
+}
+
+impl Players {
+    /// Players from specs like "human", "left=keyboard", "right=gamepad",
+    /// "bot" or "replay:session.json"
+    pub fn from_specs(specs: &[String]) -> Result<Self> {
+        let mut players = specs
+            .iter()
+            .map(|spec| parse_player(spec))
+            .collect::<Result<Vec<Player>>>()?;
+        // Someone has to be able to use the menus
+        if !players.iter().any(|player| player.source.is_human()) {
+            players.push(Player {
+                source: Box::new(KeyboardSource),
+                side: Side::Both,
+            });
+        }
+        Ok(Players { players })
+    }
+
+    /// Input for the next step of the game, from every player
+    pub fn next_input(&mut self, game: &GameState) -> Input {
+        merge_inputs(self.players.iter_mut(), game)
+    }
+
+    /// Input for the menus and screens around a game, from the people
+    /// playing only, so recordings and bots do not run ahead of the game
+    pub fn menu_input(&mut self, game: &GameState) -> Input {
+        let humans = self.players.iter_mut();
+        merge_inputs(humans.filter(|player| player.source.is_human()), game)
+    }
+
+    pub fn on_event(&mut self, event: &Event) {
+        for player in self.players.iter_mut() {
+            player.source.on_event(event);
+        }
+    }
+}
+
+/// Merge the players' inputs: the first player moving an actuator
+/// controls it, menu buttons of anyone count
+fn merge_inputs<'a>(players: impl Iterator<Item = &'a mut Player>, game: &GameState) -> Input {
+    let mut merged = Input::default();
+    for player in players {
+        let input = player.source.next_input(game);
+        for (i, value) in input.actuators.iter().enumerate() {
+            if player.side.controls(i) && merged.actuators[i] == 0. {
+                merged.actuators[i] = *value;
+            }
+        }
+        merged.menu_up |= input.menu_up;
+        merged.menu_down |= input.menu_down;
+        merged.enter |= input.enter;
+        merged.escape |= input.escape;
+    }
+    merged
+}
+
+fn parse_player(spec: &str) -> Result<Player> {
+    let (side, kind) = match spec.split_once('=') {
+        Some(("left", kind)) => (Side::Left, kind),
+        Some(("right", kind)) => (Side::Right, kind),
+        Some((side, _)) => return Err(format!("Unknown player side \"{}\"", side).into()),
+        None => (Side::Both, spec),
+    };
+    let source: Box<dyn InputSource> = match kind.split_once(':') {
+        Some(("replay", file_name)) => Box::new(ReplaySource::load(file_name)?),
+        _ if kind == "human" => Box::new(HumanSource),
+        _ if kind == "keyboard" => Box::new(KeyboardSource),
+        _ if kind == "gamepad" => Box::new(GamepadSource),
+        _ if kind == "bot" => Box::new(Autopilot::new()),
+        _ => return Err(format!("Unknown input source \"{}\"", kind).into()),
+    };
+    Ok(Player { source, side })
+}
+
+fn read_updown(bindings: &KeyBindings, up: Action, down: Action) -> f32 {
+    bindings.is_down(up) as i32 as f32 - bindings.is_down(down) as i32 as f32
+}
+
+// Keys override the analog value of touch sliders or the gamepad
+fn key_or_analog(key: f32, analog: f32) -> f32 {
+    if key != 0. {
+        key
+    } else {
+        analog
+    }
+}
+
+pub fn update_input(game: &GameState) -> Input {
+    storage::get_mut::<Players>().next_input(game)
+}
+
+pub fn update_menu_input(game: &GameState) -> Input {
+    storage::get_mut::<Players>().menu_input(game)
+}
+
+/// Let the players' input sources follow the game to its next state
+pub fn handle_event(event: &Event) {
+    if let Some(mut players) = storage::try_get_mut::<Players>() {
+        players.on_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        debug::DebugChannel,
+        game::sim,
+        replay::{ball_hash, tests::record},
+        timestep::FIXED_DELTATIME,
+    };
+
+    // Event and ball hash after every step
+    fn steps(frames: &[ReplayFrame]) -> Vec<(Option<Event>, u64)> {
+        frames
+            .iter()
+            .filter_map(|frame| match frame {
+                ReplayFrame::Step { event, hash, .. } => Some((event.clone(), *hash)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Step through the recorded level with inputs from a replay source,
+    // as the game loop does, with menus in between
+    fn play_back(replay: Replay, steps: usize) -> Vec<(Option<Event>, u64)> {
+        let mut game = GameState::with_level(replay.level.clone(), &replay.physics);
+        let mut players = Players {
+            players: vec![Player {
+                source: Box::new(ReplaySource::new(replay)),
+                side: Side::Both,
+            }],
+        };
+        let mut debug = DebugChannel::new();
+        let mut played = vec![];
+        for _ in 0..steps {
+            assert_eq!(players.menu_input(&game), Input::default());
+            let input = players.next_input(&game);
+            let event = sim::step(&mut game, &input, FIXED_DELTATIME, &mut debug);
+            played.push((event.clone(), ball_hash(&game.objects.balls)));
+            if let Some(event) = event {
+                game = sim::apply_event(game, &event);
+                players.on_event(&event);
+            }
+        }
+        played
+    }
+
+    #[test]
+    fn replay_source_plays_back_recorded_session() {
+        let replay = record(3_000);
+        let recorded = steps(&replay.frames);
+        assert!(recorded.iter().any(|(event, _)| event.is_some()));
+        assert_eq!(play_back(replay, recorded.len()), recorded);
+    }
+
+    #[test]
+    fn replay_source_drops_steps_recorded_past_a_transition() {
+        let mut replay = record(3_000);
+        let recorded = steps(&replay.frames);
+        // Steps on the finished round while the screen faded out, which the
+        // game does not run when it moves on sooner
+        let transition = replay
+            .frames
+            .iter()
+            .position(|frame| frame.transition().is_some())
+            .unwrap();
+        for _ in 0..20 {
+            let fading = ReplayFrame::Step {
+                dt: FIXED_DELTATIME,
+                input: Input {
+                    actuators: [-1., 1.],
+                    ..Default::default()
+                },
+                event: None,
+                hash: 0,
+            };
+            replay.frames.insert(transition, fading);
+        }
+        assert_eq!(play_back(replay, recorded.len()), recorded);
     }
 }
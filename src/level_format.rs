@@ -0,0 +1,284 @@
+use crate::{
+    config::{SCALE, SCREEN_H, SCREEN_W},
+    game::physics::PhysicsProfile,
+    resources::has_asset,
+    state::{GameLevelState, Hole},
+};
+use macroquad::math::Vec2;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
+
+pub const LEVEL_VERSION: u32 = 2;
+// Files written before versioning have no "version" field
+pub const LEVEL_VERSION_V1: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LevelError {
+    UnsupportedVersion(u32),
+    NoGoals,
+    InvalidParTime,
+    GoalOutOfRange { index: usize, hole: usize },
+    OverlappingHoles(usize, usize),
+    HoleOutsidePlayfield(usize),
+    InvalidHoleTimer(usize),
+    InvalidHoleShape(usize),
+    ObstacleOutsidePlayfield(usize),
+    MissingBackground(String),
+    InvalidPhysics(String),
+}
+
+/// All problems found in a level file
+#[derive(Debug)]
+pub struct InvalidLevel {
+    pub level_file: String,
+    pub errors: Vec<LevelError>,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::UnsupportedVersion(version) => {
+                write!(f, "unsupported level version {}", version)
+            }
+            LevelError::NoGoals => write!(f, "level has no goals"),
+            LevelError::InvalidParTime => write!(f, "par time must be positive"),
+            LevelError::GoalOutOfRange { index, hole } => {
+                write!(f, "goal {} points to missing hole {}", index, hole)
+            }
+            LevelError::OverlappingHoles(a, b) => write!(f, "holes {} and {} overlap", a, b),
+            LevelError::HoleOutsidePlayfield(hole) => {
+                write!(f, "hole {} is outside the playfield", hole)
+            }
+            LevelError::InvalidHoleTimer(hole) => {
+                write!(f, "hole {} needs a positive open time", hole)
+            }
+            LevelError::InvalidHoleShape(hole) => {
+                write!(f, "hole {} has an invalid shape", hole)
+            }
+            LevelError::ObstacleOutsidePlayfield(obstacle) => {
+                write!(f, "obstacle {} is outside the playfield", obstacle)
+            }
+            LevelError::MissingBackground(name) => {
+                write!(f, "background image \"{}\" not found", name)
+            }
+            LevelError::InvalidPhysics(error) => write!(f, "invalid physics: {}", error),
+        }
+    }
+}
+
+impl fmt::Display for InvalidLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid level \"{}\":", self.level_file)?;
+        for error in self.errors.iter() {
+            write!(f, " {};", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for InvalidLevel {}
+
+/// Upgrade a parsed level to `LEVEL_VERSION`
+pub fn migrate(mut level: GameLevelState) -> Result<GameLevelState, LevelError> {
+    if level.version == LEVEL_VERSION_V1 {
+        // v1 only had background, goals and holes; name the level after its
+        // file, author, difficulty and par time keep their defaults
+        if level.name.is_empty() {
+            level.name = level
+                .level_file
+                .trim_end_matches(".json")
+                .trim_start_matches("level_")
+                .to_string();
+        }
+        level.version = LEVEL_VERSION;
+    }
+    match level.version {
+        LEVEL_VERSION => Ok(level),
+        version => Err(LevelError::UnsupportedVersion(version)),
+    }
+}
+
+pub fn validate(level: &GameLevelState) -> Vec<LevelError> {
+    let mut errors = vec![];
+    if level.goals.is_empty() {
+        errors.push(LevelError::NoGoals);
+    }
+    if level.par_time <= 0. {
+        errors.push(LevelError::InvalidParTime);
+    }
+    for (index, hole) in level.goals.iter().enumerate() {
+        if *hole >= level.holes.len() {
+            errors.push(LevelError::GoalOutOfRange { index, hole: *hole });
+        }
+    }
+    let (width, height) = (SCREEN_W / SCALE, SCREEN_H / SCALE);
+    let inside = |pos: Vec2| pos.x >= 0. && pos.x <= width && pos.y >= 0. && pos.y <= height;
+    // Moving holes must stay apart anywhere along their paths
+    let swept: Vec<Vec<Hole>> = level.holes.iter().map(|hole| hole.swept(0.01)).collect();
+    for (i, hole) in level.holes.iter().enumerate() {
+        // The whole shape must fit, at every corner of the path and in between
+        let corners = hole.path.iter().map(|pos| Hole {
+            pos: *pos,
+            ..hole.clone()
+        });
+        let outlines = swept[i].iter().cloned().chain(corners);
+        if !outlines.flat_map(|hole| hole.outline()).all(inside) {
+            errors.push(LevelError::HoleOutsidePlayfield(i));
+        }
+        if !hole.shape.is_valid() {
+            errors.push(LevelError::InvalidHoleShape(i));
+        }
+        if let Some(timer) = &hole.timer {
+            if timer.open <= 0. || timer.closed < 0. {
+                errors.push(LevelError::InvalidHoleTimer(i));
+            }
+        }
+        for j in i + 1..level.holes.len() {
+            let overlaps = swept[i]
+                .iter()
+                .any(|hole| swept[j].iter().any(|other| hole.overlaps(other)));
+            if overlaps {
+                errors.push(LevelError::OverlappingHoles(i, j));
+            }
+        }
+    }
+    for (i, obstacle) in level.obstacles.iter().enumerate() {
+        if !obstacle.points().into_iter().all(inside) {
+            errors.push(LevelError::ObstacleOutsidePlayfield(i));
+        }
+    }
+    if !has_asset(&level.background_image) {
+        errors.push(LevelError::MissingBackground(
+            level.background_image.to_string(),
+        ));
+    }
+    if let Err(error) = PhysicsProfile::load(&level.physics) {
+        errors.push(LevelError::InvalidPhysics(error.to_string()));
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(json: &str, level_file: &str) -> GameLevelState {
+        let level: GameLevelState = serde_json::from_str(json).unwrap();
+        GameLevelState {
+            level_file: level_file.to_string(),
+            ..level
+        }
+    }
+
+    fn hole(x: f32, y: f32, radius: f32) -> Hole {
+        Hole {
+            pos: Vec2::new(x, y),
+            radius,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn migrate_v1_names_level_after_file() {
+        let v1 = r#"{
+            "background_image": "level_example.png",
+            "goals": [0],
+            "holes": [{"pos": [0.5, 1.0], "radius": 0.04}]
+        }"#;
+        let level = migrate(level(v1, "level_castle.json")).unwrap();
+        assert_eq!(level.version, LEVEL_VERSION);
+        assert_eq!(level.name, "castle");
+        assert_eq!(level.balls, 5);
+        assert_eq!(level.author, "");
+        assert_eq!(level.difficulty, Difficulty::Normal);
+        assert_eq!(level.par_time, 60.);
+        assert!(validate(&level).is_empty());
+    }
+
+    #[test]
+    fn migrate_keeps_metadata() {
+        let v2 = r#"{
+            "version": 2,
+            "name": "Castle",
+            "author": "Ann",
+            "difficulty": "hard",
+            "par_time": 45.0,
+            "background_image": "level_example.png",
+            "goals": [0],
+            "holes": [{"pos": [0.5, 1.0], "radius": 0.04}]
+        }"#;
+        let level = migrate(level(v2, "level_castle.json")).unwrap();
+        assert_eq!(level.name, "Castle");
+        assert_eq!(level.author, "Ann");
+        assert_eq!(level.difficulty, Difficulty::Hard);
+        assert_eq!(level.par_time, 45.);
+    }
+
+    #[test]
+    fn migrate_rejects_future_version() {
+        let v9 = r#"{"version": 9, "background_image": "", "goals": [], "holes": []}"#;
+        assert_eq!(
+            migrate(level(v9, "level_future.json")),
+            Err(LevelError::UnsupportedVersion(9))
+        );
+    }
+
+    #[test]
+    fn example_level_is_valid() {
+        let level = GameLevelState::read("level_example.json").unwrap();
+        assert_eq!(validate(&level), vec![]);
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let level = GameLevelState {
+            background_image: "missing.png".to_string(),
+            goals: vec![0, 3],
+            holes: vec![
+                hole(0.5, 1.0, 0.05),
+                hole(0.52, 1.0, 0.05),
+                // Center inside, rim past the left wall
+                hole(0.03, 1.0, 0.05),
+            ],
+            par_time: 0.,
+            ..GameLevelState::new()
+        };
+        assert_eq!(
+            validate(&level),
+            vec![
+                LevelError::InvalidParTime,
+                LevelError::GoalOutOfRange { index: 1, hole: 3 },
+                LevelError::OverlappingHoles(0, 1),
+                LevelError::HoleOutsidePlayfield(2),
+                LevelError::MissingBackground("missing.png".to_string()),
+            ]
+        );
+        let empty = GameLevelState::new();
+        assert!(validate(&empty).contains(&LevelError::NoGoals));
+    }
+
+    #[test]
+    fn validate_checks_moving_holes_along_path() {
+        let moving = Hole {
+            path: vec![Vec2::new(0.9, 0.5)],
+            speed: 0.1,
+            ..hole(0.1, 0.5, 0.05)
+        };
+        let level = GameLevelState {
+            background_image: "level_example.png".to_string(),
+            goals: vec![0],
+            holes: vec![moving, hole(0.5, 0.5, 0.05), hole(0.5, 0.7, 0.05)],
+            ..GameLevelState::new()
+        };
+        assert_eq!(validate(&level), vec![LevelError::OverlappingHoles(0, 1)]);
+    }
+}
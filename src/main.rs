@@ -3,35 +3,117 @@
 use macroquad::experimental::{collections::storage, coroutines::start_coroutine};
 use macroquad::prelude::*;
 
+mod bindings;
+mod campaign;
 mod config;
 mod debug;
 mod display;
 mod editor;
 mod game;
+mod gamepad;
+mod highscore;
+mod history;
 mod input;
+mod level_format;
+mod replay;
 mod resources;
+mod settings;
 mod sound;
 mod state;
+mod timestep;
+mod touch;
 mod transition;
 mod utils;
 use crate::{
-    config::window_conf,
-    debug::DebugData,
-    resources::Resources,
-    state::{Event, State},
+    config::{window_conf, Args},
+    debug::{DebugChannel, TuningPanel},
+    game::{autopilot::Autopilot, sim::Simulation},
+    gamepad::Gamepads,
+    input::Players,
+    replay::{Recorder, Replay},
+    resources::{AssetSource, Resources},
+    settings::Settings,
+    state::{Event, GameState, State},
+    timestep::{Timestep, FIXED_DELTATIME},
+    touch::TouchSliders,
     utils::return_ok_if_some,
 };
 use std::error::Error;
 
+// Ten minutes of game time
+const PROVE_MAX_STEPS: usize = 75_000;
+
 pub type Result<T> = ::std::result::Result<T, Box<dyn Error>>;
-const TARGET_DELTATIME: f32 = 0.008;
 
-#[macroquad::main(window_conf)]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    let args = Args::parse();
+    storage::store(AssetSource::new(args.assets.as_deref()));
+    // Batch tools run headless, without opening a window
+    if let Some(file_name) = &args.replay {
+        return check_replay(file_name);
+    }
+    if let Some(level_file) = &args.prove {
+        return prove_level(level_file);
+    }
+    macroquad::Window::from_config(window_conf(), async move {
+        if let Err(err) = run_app(args).await {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    });
+    Ok(())
+}
+
+fn check_replay(file_name: &str) -> Result<()> {
+    let playback = Replay::load(file_name)?.play()?;
+    println!(
+        "{} steps, events {:?}",
+        playback.trajectory.len(),
+        playback.events
+    );
+    Ok(())
+}
+
+fn prove_level(level_file: &str) -> Result<()> {
+    let mut sim = Simulation::new(GameState::load(level_file)?);
+    let events = sim.run(&mut Autopilot::new(), FIXED_DELTATIME, PROVE_MAX_STEPS);
+    println!("events {:?}", events);
+    if !events.contains(&Event::GameCompleted) {
+        return Err(format!("Autopilot did not clear \"{}\"", level_file).into());
+    }
+    Ok(())
+}
+
+async fn run_app(args: Args) -> Result<()> {
+    if let Some(file_name) = args.record {
+        storage::store(Recorder::new(&file_name));
+    }
+    storage::store(Timestep::new());
+    storage::store(TuningPanel::new());
+    storage::store(Gamepads::new());
+    let settings = Settings::load();
+    let players = match args.players.is_empty() {
+        true => Players::from_specs(&settings.players).or_else(|err| {
+            // A bad entry in the settings, e.g. a missing replay file, should not stop the game
+            eprintln!("{}, playing with the default controls", err);
+            Players::from_specs(&Settings::default().players)
+        })?,
+        false => Players::from_specs(&args.players)?,
+    };
+    storage::store(players);
+    storage::store(settings);
+    storage::store(TouchSliders::new());
+    storage::store(match args.trace {
+        Some(file_name) => DebugChannel::with_trace(&file_name)?,
+        None => DebugChannel::new(),
+    });
+
     let mut state = State::Initial;
     let mut event = Some(Event::AppInitialized);
     while let Some(ev) = event.take() {
-        state = state.transition(ev);
+        state = state.transition(ev.clone());
+        input::handle_event(&ev);
+        replay::record_transition(&state, ev)?;
         if let State::Terminating = state {
             break;
         }
@@ -40,7 +122,7 @@ async fn main() -> Result<()> {
         sound::play_event_sound(&event);
         run_with_transition(&mut state, false).await?;
     }
-    Ok::<(), _>(())
+    Ok(())
 }
 
 async fn run(state: &mut State) -> Result<Option<Event>> {
@@ -69,7 +151,6 @@ async fn run(state: &mut State) -> Result<Option<Event>> {
                     .expect("Failed to load resources");
                 storage::store(resources);
             });
-            storage::store(Vec::<DebugData>::new());
 
             while resources_future.is_done() == false {
                 draw(state);
@@ -77,7 +158,13 @@ async fn run(state: &mut State) -> Result<Option<Event>> {
             }
             Ok(Some(Event::AppLoaded))
         }
-        State::Menu(_, _) | State::Game(_, _) | State::Editor(_, _) => loop {
+        State::Menu(_, _)
+        | State::Game(_, _)
+        | State::PlayTest(_, _, _)
+        | State::Editor(_, _)
+        | State::Initials(_, _)
+        | State::HighScores(_, _)
+        | State::Controls(_, _) => loop {
             return_ok_if_some!(update(state).await?);
             draw(state);
             next_frame().await
@@ -111,24 +198,56 @@ async fn run_with_transition(state: &mut State, out: bool) -> Result<Option<Even
 
 async fn update(state: &mut State) -> Result<Option<Event>> {
     let (frames, dt) = calculate_frames(state);
-    for _ in 0..frames {
-        let input = input::update_input();
+    if let State::Game(game, _) | State::PlayTest(game, _, _) = state {
+        // once per frame, so key presses are not repeated for each step
+        debug::update_tuning(&mut game.physics, &game.level.physics);
+    }
+    // Only steps of a game take the inputs of recordings and bots, and are recorded
+    let playing = matches!(state, State::Game(_, _) | State::PlayTest(_, _, _));
+    for step in 0..frames {
+        let input = match (state.game(), playing) {
+            (Some(game), true) => input::update_input(game),
+            (Some(game), false) => input::update_menu_input(game),
+            (None, _) => return Ok(None),
+        };
         match state {
             State::Menu(game, menu) => {
                 game::game::update_camera(game);
                 return_ok_if_some!(game::menu::update_menu(menu, &input));
             }
-            State::Game(game, display) | State::Score(game, display) => {
+            State::Game(game, display)
+            | State::Score(game, display)
+            | State::PlayTest(game, display, _) => {
                 if input.escape {
-                    return Ok(Some(Event::GameEnded));
+                    return Ok(Some(Event::GameQuit));
                 }
                 display::update_display(display);
-                return_ok_if_some!(game::game::update_game(game, &input, dt));
+                let event = game::game::update_game(game, &input, dt);
+                if playing {
+                    replay::record_step(&input, dt, &event, &game.objects.balls);
+                }
+                if event.is_some() {
+                    // the steps left over run in the state the event leads to
+                    storage::get_mut::<Timestep>().give_back(frames - step - 1);
+                    return Ok(event);
+                }
             }
             State::Editor(game, editor) => {
                 game::game::update_camera(game);
                 return_ok_if_some!(editor::update_editor(game, editor));
             }
+            State::Initials(game, initials) => {
+                game::game::update_camera(game);
+                return_ok_if_some!(game::scores::update_initials(initials, &input));
+            }
+            State::HighScores(game, scores) => {
+                game::game::update_camera(game);
+                return_ok_if_some!(game::scores::update_scores(scores, &input));
+            }
+            State::Controls(game, controls) => {
+                game::game::update_camera(game);
+                return_ok_if_some!(game::controls::update_controls(controls, &input));
+            }
             _ => {}
         }
     }
@@ -138,10 +257,10 @@ async fn update(state: &mut State) -> Result<Option<Event>> {
 fn calculate_frames(state: &State) -> (i32, f32) {
     let dt = get_frame_time();
     match state {
-        State::Game(_, _) => {
-            // divide frame into substeps to improve physics collision handling
-            let substeps = i32::max(1, (dt / TARGET_DELTATIME).ceil() as i32);
-            (substeps, dt / substeps as f32)
+        State::Game(_, _) | State::Score(_, _) | State::PlayTest(_, _, _) => {
+            // run physics in fixed steps, independent of the frame rate
+            let steps = storage::get_mut::<Timestep>().advance(dt);
+            (steps, FIXED_DELTATIME)
         }
         _ => (1, dt),
     }
@@ -186,7 +305,7 @@ fn draw(state: &State) {
         }
         State::Menu(game, menu) => {
             clear_background(BLACK);
-            game::game::draw_game(&game);
+            game::game::draw_game(game, 1.);
             draw_rectangle(
                 0.,
                 0.,
@@ -196,11 +315,51 @@ fn draw(state: &State) {
             );
             game::menu::draw_menu(&menu);
         }
-        State::Game(game, display) | State::Score(game, display) => {
+        State::Initials(game, initials) => {
+            clear_background(BLACK);
+            game::game::draw_game(game, 1.);
+            draw_rectangle(
+                0.,
+                0.,
+                config::SCREEN_W,
+                config::SCREEN_H,
+                Color::from_rgba(0, 0, 0, 100),
+            );
+            game::scores::draw_initials(initials);
+        }
+        State::HighScores(game, scores) => {
+            clear_background(BLACK);
+            game::game::draw_game(game, 1.);
+            draw_rectangle(
+                0.,
+                0.,
+                config::SCREEN_W,
+                config::SCREEN_H,
+                Color::from_rgba(0, 0, 0, 100),
+            );
+            game::scores::draw_scores(scores);
+        }
+        State::Controls(game, controls) => {
+            clear_background(BLACK);
+            game::game::draw_game(game, 1.);
+            draw_rectangle(
+                0.,
+                0.,
+                config::SCREEN_W,
+                config::SCREEN_H,
+                Color::from_rgba(0, 0, 0, 100),
+            );
+            game::controls::draw_controls(controls);
+        }
+        State::Game(game, display)
+        | State::Score(game, display)
+        | State::PlayTest(game, display, _) => {
             clear_background(BLACK);
-            game::game::draw_game(&game);
-            // debug::draw_debug();
+            let alpha = storage::get::<Timestep>().alpha();
+            game::game::draw_game(game, alpha);
             display::draw_display(&game, &display);
+            touch::draw_sliders(&storage::get::<TouchSliders>());
+            debug::draw_tuning(&game.physics);
         }
         State::Editor(game, editor) => {
             clear_background(BLACK);
@@ -0,0 +1,313 @@
+use crate::{
+    debug::DebugChannel,
+    game::{physics::PhysicsProfile, sim},
+    input::Input,
+    state::{Ball, Event, GameLevelState, GameState, State},
+    Result,
+};
+use macroquad::{experimental::collections::storage, math::Vec3};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+
+pub const REPLAY_VERSION: u32 = 4;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub level_file: String,
+    /// Level as recorded, so editing or replacing the file does not break the replay
+    pub level: GameLevelState,
+    /// Physics in effect when recording, which may differ between machines
+    pub physics: PhysicsProfile,
+    pub frames: Vec<ReplayFrame>,
+}
+
+/// Either one simulation substep as it ran in the game loop,
+/// or a state transition applied between substeps.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReplayFrame {
+    Step {
+        dt: f32,
+        input: Input,
+        event: Option<Event>,
+        /// `ball_hash` of the balls after the step
+        hash: u64,
+    },
+    Transition(Event),
+    /// Transition to the next level of a campaign
+    Level {
+        event: Event,
+        level_file: String,
+        level: Box<GameLevelState>,
+        physics: PhysicsProfile,
+    },
+}
+
+pub struct Playback {
+    pub events: Vec<Event>,
+    /// Ball positions after every step
+    pub trajectory: Vec<Vec<Vec3>>,
+}
+
+// Stored in macroquad storage while recording is enabled
+pub struct Recorder {
+    pub file_name: String,
+    pub replay: Option<Replay>,
+    pub level_file: String,
+}
+
+impl Replay {
+    pub fn new(level: &GameLevelState, physics: &PhysicsProfile) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            level_file: level.level_file.to_string(),
+            level: level.clone(),
+            physics: physics.clone(),
+            frames: vec![],
+        }
+    }
+
+    pub fn load(file_name: &str) -> Result<Self> {
+        let file = File::open(file_name)?;
+        let mut replay: Replay = serde_json::from_reader(file)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "Unsupported replay version {} in \"{}\"",
+                replay.version, file_name
+            )
+            .into());
+        }
+        // File names are not part of the level data
+        replay.level.level_file = replay.level_file.to_string();
+        for frame in replay.frames.iter_mut() {
+            if let ReplayFrame::Level {
+                level_file, level, ..
+            } = frame
+            {
+                level.level_file = level_file.to_string();
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, file_name: &str) -> Result<()> {
+        let file = File::create(file_name)?;
+        serde_json::to_writer(&file, self)?;
+        Ok(())
+    }
+
+    /// Run the recorded frames through the simulation and check that
+    /// every step emits the same event and moves the balls the same way
+    /// it did when recorded.
+    pub fn play(&self) -> Result<Playback> {
+        let mut game = GameState::with_level(self.level.clone(), &self.physics);
+        let mut debug = DebugChannel::new();
+        let mut playback = Playback {
+            events: vec![],
+            trajectory: vec![],
+        };
+        for (i, frame) in self.frames.iter().enumerate() {
+            match frame {
+                ReplayFrame::Step {
+                    dt,
+                    input,
+                    event,
+                    hash,
+                } => {
+                    let played = sim::step(&mut game, input, *dt, &mut debug);
+                    if played != *event {
+                        return Err(format!(
+                            "Replay diverged at frame {}: recorded {:?}, played {:?}",
+                            i, event, played
+                        )
+                        .into());
+                    }
+                    if ball_hash(&game.objects.balls) != *hash {
+                        return Err(format!(
+                            "Replay diverged at frame {}: balls moved differently",
+                            i
+                        )
+                        .into());
+                    }
+                    playback.events.extend(played);
+                    playback
+                        .trajectory
+                        .push(game.objects.balls.iter().map(|ball| ball.pos).collect());
+                }
+                ReplayFrame::Transition(event) => {
+                    game = sim::apply_event(game, event);
+                }
+                ReplayFrame::Level {
+                    event,
+                    level,
+                    physics,
+                    ..
+                } => {
+                    let previous = sim::apply_event(game, event);
+                    game = GameState::with_level(level.as_ref().clone(), physics)
+                        .with_progress_from(&previous);
+                }
+            }
+        }
+        Ok(playback)
+    }
+}
+
+impl ReplayFrame {
+    /// Event of a transition, none for a step
+    pub fn transition(&self) -> Option<&Event> {
+        match self {
+            ReplayFrame::Step { .. } => None,
+            ReplayFrame::Transition(event) | ReplayFrame::Level { event, .. } => Some(event),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new(file_name: &str) -> Self {
+        Recorder {
+            file_name: file_name.to_string(),
+            replay: None,
+            level_file: String::new(),
+        }
+    }
+}
+
+/// Whether a session is being recorded right now
+pub fn is_recording() -> bool {
+    storage::try_get::<Recorder>().is_some_and(|recorder| recorder.replay.is_some())
+}
+
+/// Fingerprint of the exact position, velocity and state of every ball
+pub fn ball_hash(balls: &[Ball]) -> u64 {
+    // FNV-1a, stable across platforms and builds unlike `DefaultHasher`
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for ball in balls {
+        let values = ball.pos.to_array().into_iter().chain(ball.vel.to_array());
+        let bytes = values
+            .flat_map(|value| value.to_bits().to_le_bytes())
+            .chain([ball.active as u8]);
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+pub fn record_step(input: &Input, dt: f32, event: &Option<Event>, balls: &[Ball]) {
+    if let Some(mut recorder) = storage::try_get_mut::<Recorder>() {
+        if let Some(replay) = recorder.replay.as_mut() {
+            replay.frames.push(ReplayFrame::Step {
+                dt,
+                input: input.clone(),
+                event: event.clone(),
+                hash: ball_hash(balls),
+            });
+        }
+    }
+}
+
+/// Start, extend or finish the recording after `event` moved the app to `state`
+pub fn record_transition(state: &State, event: Event) -> Result<()> {
+    let mut recorder = match storage::try_get_mut::<Recorder>() {
+        Some(recorder) => recorder,
+        None => return Ok(()),
+    };
+    match state {
+        State::Game(game, _) | State::Score(game, _) => {
+            let level_file = game.level.level_file.to_string();
+            let frame = if level_file == recorder.level_file {
+                ReplayFrame::Transition(event)
+            } else {
+                ReplayFrame::Level {
+                    event,
+                    level_file: level_file.to_string(),
+                    level: Box::new(game.level.clone()),
+                    physics: game.physics.clone(),
+                }
+            };
+            if let Some(replay) = recorder.replay.as_mut() {
+                replay.frames.push(frame);
+            } else {
+                recorder.replay = Some(Replay::new(&game.level, &game.physics));
+            }
+            recorder.level_file = level_file;
+        }
+        _ => {
+            if let Some(replay) = recorder.replay.take() {
+                replay.save(&recorder.file_name)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::timestep::FIXED_DELTATIME;
+
+    const LEVEL: &str = "level_example.json";
+
+    // Record a session the way the game loop does, raising and tilting the rod
+    pub(crate) fn record(steps: usize) -> Replay {
+        let mut game = GameState::load(LEVEL).unwrap();
+        // Differs from the profile loaded when playing back
+        game.physics.gravity.y = 8.0;
+        let mut replay = Replay::new(&game.level, &game.physics);
+        let mut debug = DebugChannel::new();
+        for i in 0..steps {
+            let input = Input {
+                actuators: [1.0, 1.0 - (i as f32 * 0.01).sin()],
+                ..Default::default()
+            };
+            let event = sim::step(&mut game, &input, FIXED_DELTATIME, &mut debug);
+            replay.frames.push(ReplayFrame::Step {
+                dt: FIXED_DELTATIME,
+                input,
+                event: event.clone(),
+                hash: ball_hash(&game.objects.balls),
+            });
+            if let Some(event) = event {
+                game = sim::apply_event(game, &event);
+                replay.frames.push(ReplayFrame::Transition(event));
+            }
+        }
+        replay
+    }
+
+    #[test]
+    fn replay_round_trip() {
+        let replay = record(3_000);
+        let file = std::env::temp_dir().join("arcade_game_replay_round_trip.json");
+        let file_name = file.to_str().unwrap();
+        replay.save(file_name).unwrap();
+        let loaded = Replay::load(file_name).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(loaded, replay);
+
+        let playback = loaded.play().unwrap();
+        assert_eq!(playback.trajectory.len(), 3_000);
+        assert!(!playback.events.is_empty());
+    }
+
+    #[test]
+    fn replay_plays_embedded_level() {
+        let mut replay = record(500);
+        replay.level_file = "missing_level.json".to_string();
+        let playback = replay.play().unwrap();
+        assert_eq!(playback.trajectory.len(), 500);
+    }
+
+    #[test]
+    fn replay_detects_diverging_trajectory() {
+        let mut replay = record(500);
+        // Same events, but the right side moves differently at one step
+        if let Some(ReplayFrame::Step { input, .. }) = replay.frames.get_mut(100) {
+            input.actuators[1] = -1.0;
+        }
+        let err = replay.play().err().expect("replay diverges").to_string();
+        assert!(err.contains("frame 100"), "{}", err);
+    }
+}
@@ -1,15 +1,27 @@
-use crate::Result;
+use crate::{config, Result};
 use macroquad::{
     audio::{load_sound_from_bytes, Sound},
+    experimental::collections::storage,
     prelude::*,
 };
 use rust_embed::RustEmbed;
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::PathBuf,
+};
 
 #[derive(RustEmbed)]
 #[folder = "assets/"]
 pub struct Asset;
 
+/// Asset directories searched before the embedded assets,
+/// in order of precedence: command line path, then user levels directory
+pub struct AssetSource {
+    pub dirs: Vec<PathBuf>,
+}
+
 pub struct Resources {
     pub actuator_fg: Texture2D,
     pub actuator_bg: Texture2D,
@@ -29,6 +41,73 @@ pub struct SoundResources {
     pub sounds: HashMap<String, Sound>,
 }
 
+impl AssetSource {
+    pub fn new(path: Option<&str>) -> Self {
+        AssetSource {
+            dirs: path
+                .map(PathBuf::from)
+                .into_iter()
+                .chain(Self::user_levels_dir())
+                .collect(),
+        }
+    }
+
+    pub fn user_levels_dir() -> Option<PathBuf> {
+        config::user_data_dir().map(|dir| dir.join("levels"))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Cow<'static, [u8]>> {
+        for dir in self.dirs.iter() {
+            if let Ok(data) = fs::read(dir.join(name)) {
+                return Some(Cow::Owned(data));
+            }
+        }
+        Asset::get(name).map(|file| file.data)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.dirs.iter().any(|dir| dir.join(name).is_file()) || Asset::get(name).is_some()
+    }
+
+    /// Names of all assets, from every layer
+    pub fn names(&self) -> BTreeSet<String> {
+        let mut names: BTreeSet<String> = Asset::iter().map(|name| name.to_string()).collect();
+        for dir in self.dirs.iter() {
+            if let Ok(entries) = fs::read_dir(dir) {
+                names.extend(
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .filter_map(|entry| entry.file_name().into_string().ok()),
+                );
+            }
+        }
+        names
+    }
+}
+
+/// Look up an asset in the layered source, or the embedded assets
+/// if no source has been stored
+pub fn load_asset(name: &str) -> Option<Cow<'static, [u8]>> {
+    match storage::try_get::<AssetSource>() {
+        Some(source) => source.get(name),
+        None => Asset::get(name).map(|file| file.data),
+    }
+}
+
+pub fn has_asset(name: &str) -> bool {
+    match storage::try_get::<AssetSource>() {
+        Some(source) => source.contains(name),
+        None => Asset::get(name).is_some(),
+    }
+}
+
+fn asset_names() -> BTreeSet<String> {
+    match storage::try_get::<AssetSource>() {
+        Some(source) => source.names(),
+        None => Asset::iter().map(|name| name.to_string()).collect(),
+    }
+}
+
 impl Resources {
     pub async fn new() -> Result<Self> {
         let actuator_fg_data = Asset::get("actuator_fg.png").ok_or("Could not load actuator")?;
@@ -74,12 +153,12 @@ impl Resources {
 
     fn load_backgrounds() -> Result<HashMap<String, Texture2D>> {
         let mut backgrounds: HashMap<String, Texture2D> = HashMap::new();
-        let file_names =
-            Asset::iter().filter(|name| name.starts_with("level_") && name.ends_with(".png"));
+        let file_names = asset_names()
+            .into_iter()
+            .filter(|name| name.starts_with("level_") && name.ends_with(".png"));
         for name in file_names {
-            let data = Asset::get(name.as_ref())
-                .ok_or(format!("Could not load background \"{}\"", name))?
-                .data;
+            let data =
+                load_asset(&name).ok_or(format!("Could not load background \"{}\"", name))?;
             backgrounds.insert(
                 name.to_string(),
                 Texture2D::from_file_with_format(&data, None),
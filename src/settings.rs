@@ -0,0 +1,50 @@
+use crate::{bindings::KeyBindings, config, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// User preferences, persisted in the user data directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub bindings: KeyBindings,
+    /// Input source of each player, see `input::Players::from_specs`
+    pub players: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            bindings: KeyBindings::default(),
+            players: vec!["human".to_string()],
+        }
+    }
+}
+
+impl Settings {
+    /// Load saved settings, using defaults for anything missing
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().ok_or("No user data directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(&file, self)?;
+        Ok(())
+    }
+
+    fn path() -> Option<PathBuf> {
+        config::user_data_dir().map(|dir| dir.join(SETTINGS_FILE))
+    }
+}
@@ -11,7 +11,7 @@ pub fn play_event_sound(event: &Option<Event>) {
                     .get("sound_example.wav")
                     .expect("Resource for sound_game_completed not found"),
             ),
-            Event::GameEnded => play_sound_once(
+            Event::GameEnded | Event::GameQuit => play_sound_once(
                 *resources
                     .sounds
                     .get("sound_example.wav")
@@ -29,7 +29,7 @@ pub fn play_event_sound(event: &Option<Event>) {
                     .get("sound_example.wav")
                     .expect("Resource for sound_pickup not found"),
             ),
-            Event::MenuSelected(_) => play_sound_once(
+            Event::MenuSelected(_) | Event::LevelSelected(_) => play_sound_once(
                 *resources
                     .sounds
                     .get("sound_example.wav")
@@ -1,14 +1,23 @@
 use crate::{
+    campaign::{self, Campaign, CampaignProgress, CAMPAIGN_FILE},
     config::{SCALE, SCREEN_H, SCREEN_W},
-    game::balls::BALL_RADIUS,
+    game::physics::PhysicsProfile,
     game::rod::ACTUATOR_WIDTH,
-    resources::Asset,
+    game::shapes::HoleShape,
+    highscore::{self, HighScores, PendingScore},
+    history::History,
+    level_format::{self, Difficulty, InvalidLevel, LEVEL_VERSION, LEVEL_VERSION_V1},
+    resources::load_asset,
+    settings::Settings,
+    Result,
 };
 use macroquad::{
+    experimental::collections::storage,
     math::{Vec2, Vec3},
     time::get_time,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 #[derive(Debug, PartialEq)]
 #[allow(dead_code)]
@@ -19,24 +28,36 @@ pub enum State {
     Menu(GameState, MenuState),
     Game(GameState, DisplayState),
     Score(GameState, DisplayState),
+    /// Game started from the editor, returning to it afterwards
+    PlayTest(GameState, DisplayState, EditorState),
     Editor(GameState, EditorState),
+    Initials(GameState, InitialsState),
+    HighScores(GameState, HighScoreState),
+    Controls(GameState, ControlsState),
     Terminating,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
     AppInitialized,
     AppLoaded,
     SplashTimeout,
     MenuSelected(String),
+    LevelSelected(usize),
+    /// Last ball lost
     GameEnded,
+    /// Game left with Escape before it ended
+    GameQuit,
     GameCompleted,
     RoundCompleted,
     RoundLost,
     EditorClosed,
+    PlayTestStarted,
+    InitialsEntered,
+    ScoresClosed,
+    ControlsClosed,
 }
 
-pub const GRAVITY: (f32, f32, f32) = (0.0, 9.81, -0.1); // m/s
 impl State {
     pub fn transition(self, event: Event) -> Self {
         match (self, event) {
@@ -45,34 +66,88 @@ impl State {
             (State::Splash, Event::SplashTimeout) => {
                 return State::Menu(GameState::new(), MenuState::main());
             }
-            (State::Menu(_, _), Event::MenuSelected(item)) => match item.as_str() {
-                "start" => {
-                    return State::Game(
-                        GameState::load("level_example.json").with_help(),
-                        DisplayState::new(),
-                    );
+            (State::Menu(game, menu), Event::MenuSelected(item)) => match item.as_str() {
+                "start" => start_campaign_level(0, game, menu),
+                "levels" => match Campaign::load(CAMPAIGN_FILE) {
+                    Ok(campaign) => State::Menu(
+                        game,
+                        MenuState::levels(&campaign, &CampaignProgress::load()),
+                    ),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        State::Menu(game, menu)
+                    }
+                },
+                "back" => State::Menu(game, MenuState::main()),
+                "scores" => State::HighScores(game, HighScoreState::new(HighScores::load(), None)),
+                "controls" => {
+                    State::Controls(game, ControlsState::new(storage::get::<Settings>().clone()))
                 }
                 "quit" => State::Terminating,
                 "editor" => State::Editor(
-                    GameState::load("level_new.json"),
-                    EditorState { radius: 0.04 },
+                    GameState {
+                        level: GameLevelState::read("level_new.json")
+                            .unwrap_or_else(|_| GameLevelState::new()),
+                        ..Default::default()
+                    },
+                    EditorState::new(),
                 ),
                 _ => unreachable!(),
             },
+            (State::Menu(game, menu), Event::LevelSelected(index)) => {
+                start_campaign_level(index, game, menu)
+            }
             (State::Game(game, _), Event::GameCompleted) => {
-                let game = game.next_round();
-                let score = game.progress.score;
-                return State::Score(
-                    game.reset_round(),
-                    DisplayState::messages(vec![
-                        &format!("score {}", score),
-                        &format!("score {}", score),
-                        "game over",
-                    ]),
-                );
+                let mut game = game.next_round();
+                game.progress.results.push(LevelResult {
+                    level_file: game.level.level_file.to_string(),
+                    name: game.level.name.to_string(),
+                    score: game
+                        .progress
+                        .score
+                        .saturating_sub(game.progress.start_score),
+                });
+                match campaign::complete_level(&game) {
+                    Ok(Some(next)) => {
+                        let level = next.progress.campaign_level.unwrap_or(0) + 1;
+                        return State::Game(
+                            next,
+                            DisplayState::message(&format!("level {}", level)),
+                        );
+                    }
+                    Ok(None) => {}
+                    Err(err) => eprintln!("{}", err),
+                }
+                game_over(game)
+            }
+            (State::Score(game, _), Event::GameEnded | Event::GameQuit) => {
+                let pending = highscore::pending_scores(&game, &HighScores::load());
+                if pending.is_empty() {
+                    return State::Menu(game.reset_round(), MenuState::main());
+                }
+                State::Initials(game.reset_round(), InitialsState::new(pending))
             }
-            (State::Game(game, _) | State::Score(game, _), Event::GameEnded) => {
-                return State::Menu(game.reset_round(), MenuState::main());
+            (State::Game(game, _), Event::GameEnded) => game_over(game),
+            (State::Game(game, _), Event::GameQuit) => {
+                State::Menu(game.reset_round(), MenuState::main())
+            }
+            (State::Initials(game, initials), Event::InitialsEntered) => {
+                let mut scores = HighScores::load();
+                for pending in initials.pending.iter() {
+                    scores.insert(pending, &initials.initials());
+                }
+                if let Err(err) = scores.save() {
+                    eprintln!("{}", err);
+                }
+                let key = initials.pending.first().map(|pending| pending.key.as_str());
+                let scores = HighScoreState::new(scores, key);
+                State::HighScores(game, scores)
+            }
+            (State::HighScores(game, _), Event::ScoresClosed) => {
+                State::Menu(game, MenuState::main())
+            }
+            (State::Controls(game, _), Event::ControlsClosed) => {
+                State::Menu(game, MenuState::main())
             }
             (State::Game(game, _), Event::RoundLost) => {
                 let display = match game.progress.balls_left {
@@ -84,20 +159,84 @@ impl State {
             (State::Game(game, _), Event::RoundCompleted) => {
                 return State::Game(game.next_round(), DisplayState::message("great"));
             }
-            (State::Editor(game, _), Event::EditorClosed) => {
-                return State::Game(game.reset_round(), DisplayState::new());
+            (State::Editor(_, _), Event::EditorClosed) => {
+                State::Menu(GameState::new(), MenuState::main())
+            }
+            (State::Editor(game, editor), Event::PlayTestStarted) => {
+                let game = GameState {
+                    physics: PhysicsProfile::load(&game.level.physics).unwrap_or_default(),
+                    level: game.level,
+                    ..Default::default()
+                };
+                State::PlayTest(game.reset_round(), DisplayState::message("test"), editor)
+            }
+            (State::PlayTest(game, _, editor), Event::RoundCompleted) => {
+                State::PlayTest(game.next_round(), DisplayState::message("great"), editor)
+            }
+            (
+                State::PlayTest(game, _, editor),
+                Event::RoundLost | Event::GameCompleted | Event::GameEnded | Event::GameQuit,
+            ) => {
+                let game = GameState {
+                    physics: PhysicsProfile::load(&game.level.physics).unwrap_or_default(),
+                    level: game.level,
+                    ..Default::default()
+                };
+                State::Editor(game, editor)
             }
             (state, _) => state,
         }
     }
+
+    /// The game shown in this state, if any
+    pub fn game(&self) -> Option<&GameState> {
+        match self {
+            State::Menu(game, _)
+            | State::Game(game, _)
+            | State::Score(game, _)
+            | State::PlayTest(game, _, _)
+            | State::Editor(game, _)
+            | State::Initials(game, _)
+            | State::HighScores(game, _)
+            | State::Controls(game, _) => Some(game),
+            State::Initial | State::Splash | State::Loading | State::Terminating => None,
+        }
+    }
+}
+
+/// Show the final score, then offer to enter initials for high scores
+fn game_over(game: GameState) -> State {
+    let score = game.progress.score;
+    State::Score(
+        game.reset_round(),
+        DisplayState::messages(vec![
+            &format!("score {}", score),
+            &format!("score {}", score),
+            "game over",
+        ]),
+    )
+}
+
+fn start_campaign_level(index: usize, game: GameState, menu: MenuState) -> State {
+    match Campaign::load(CAMPAIGN_FILE).and_then(|campaign| campaign.load_level(index)) {
+        Ok(level) => State::Game(level.with_help(), DisplayState::new()),
+        Err(err) => {
+            eprintln!("{}", err);
+            State::Menu(game, menu)
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct GameState {
     pub objects: GameObjectState,
+    /// Objects before the last physics step, for interpolated rendering
+    pub previous: Option<GameObjectState>,
     pub camera: GameCameraState,
     pub level: GameLevelState,
     pub progress: GameProgressState,
+    /// Base physics profile with the level's overrides applied
+    pub physics: PhysicsProfile,
 }
 
 impl GameState {
@@ -108,11 +247,21 @@ impl GameState {
         }
     }
 
-    pub fn load(level_file: &str) -> Self {
-        GameState {
-            level: GameLevelState::from_file(level_file),
+    pub fn load(level_file: &str) -> Result<Self> {
+        let level = GameLevelState::from_file(level_file)?;
+        let physics = PhysicsProfile::load(&level.physics)?;
+        Ok(GameState::with_level(level, &physics))
+    }
+
+    /// Start a game on `level` with the given physics, e.g. as recorded in a replay
+    pub fn with_level(level: GameLevelState, physics: &PhysicsProfile) -> Self {
+        let mut game = GameState {
+            physics: physics.clone(),
+            level,
             ..Default::default()
-        }
+        };
+        game.progress.balls_left = game.level.balls.saturating_sub(1);
+        game.reset_round()
     }
 
     pub fn reset_round(mut self) -> Self {
@@ -129,14 +278,20 @@ impl GameState {
                 vel: 0.0,
             },
         ];
-        self.objects.balls = vec![Ball::new()];
-        self.progress.start_time = get_time() + 1.;
+        let balls = self.level.multiball.get(self.progress.goal_index);
+        self.objects.balls = Ball::spawn(balls.copied().unwrap_or(1).max(1), &self.physics);
+        self.previous = None;
+        self.progress.start_time = self.progress.elapsed + 1.;
+        self.progress.multiplier = 1;
+        self.progress.penalty_time = 0.;
+        self.objects.holes = self.level.holes.iter().map(|hole| hole.at(0.)).collect();
         self
     }
 
     pub fn next_round(mut self) -> Self {
         self.progress.goal_index += 1;
-        self.progress.score += self.progress.time() as u16;
+        let time_score = self.progress.time() as u16;
+        self.progress.score += time_score.saturating_mul(self.progress.multiplier);
         self.reset_round()
     }
 
@@ -144,6 +299,15 @@ impl GameState {
         *self.level.goals.get(self.progress.goal_index).unwrap_or(&0)
     }
 
+    /// Carry score and remaining balls over from the previous level
+    pub fn with_progress_from(mut self, previous: &GameState) -> Self {
+        self.progress.score = previous.progress.score;
+        self.progress.start_score = previous.progress.score;
+        self.progress.balls_left = previous.progress.balls_left;
+        self.progress.results = previous.progress.results.clone();
+        self
+    }
+
     pub fn with_help(self) -> Self {
         let mut state = GameState { ..self };
         state.progress.show_help = true;
@@ -153,16 +317,23 @@ impl GameState {
 
 impl Default for GameState {
     fn default() -> Self {
+        let physics = PhysicsProfile::default();
         GameState {
             progress: GameProgressState {
-                start_time: get_time() + 1.,
+                elapsed: 0.,
+                start_time: 1.,
                 goal_index: 0,
                 score: 0,
                 balls_left: 4,
                 show_help: false,
+                campaign_level: None,
+                start_score: 0,
+                results: vec![],
+                multiplier: 1,
+                penalty_time: 0.,
             },
             objects: GameObjectState {
-                balls: vec![Ball::new()],
+                balls: vec![Ball::new(&physics)],
                 actuators: [
                     Actuator {
                         pos: Vec2::new(ACTUATOR_WIDTH / 2., (SCREEN_H - 100.0) / SCALE),
@@ -176,104 +347,262 @@ impl Default for GameState {
                         vel: 0.0,
                     },
                 ],
+                holes: vec![],
             },
+            previous: None,
             camera: GameCameraState {
                 pos: Vec2::new(0.0, 0.0),
                 vel: Vec2::new(0.0, 0.0),
                 rotation: 0.,
             },
             level: GameLevelState::new(),
+            physics,
         }
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct MenuState {
+    pub kind: MenuKind,
     pub selected: usize,
     pub options: Vec<String>,
+    /// Indices of options that can not be selected
+    pub disabled: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MenuKind {
+    Main,
+    Levels,
 }
 
 impl MenuState {
     pub fn main() -> Self {
         MenuState {
+            kind: MenuKind::Main,
             selected: 0,
             options: vec![
                 "Start".to_string(),
-                // "Editor".to_string(),
+                "Levels".to_string(),
+                "Scores".to_string(),
+                "Controls".to_string(),
+                "Editor".to_string(),
                 "Quit".to_string(),
             ],
+            disabled: vec![],
+        }
+    }
+
+    pub fn levels(campaign: &Campaign, progress: &CampaignProgress) -> Self {
+        let mut options: Vec<String> = campaign
+            .levels
+            .iter()
+            .map(|level| level.name.to_string())
+            .collect();
+        options.push("Back".to_string());
+        MenuState {
+            kind: MenuKind::Levels,
+            selected: 0,
+            options,
+            disabled: (0..campaign.levels.len())
+                .filter(|i| !campaign.is_unlocked(*i, progress))
+                .collect(),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GameObjectState {
     pub balls: Vec<Ball>,
     pub actuators: [Actuator; 2],
+    /// Level holes as they are at the current time of the round
+    pub holes: Vec<Hole>,
 }
 
-#[derive(Debug, PartialEq)]
+impl GameObjectState {
+    /// Blend object positions between `self` and the `next` physics step
+    pub fn lerp(&self, next: &GameObjectState, alpha: f32) -> GameObjectState {
+        if self.balls.len() != next.balls.len() {
+            return next.clone();
+        }
+        let mut objects = next.clone();
+        for (ball, prev) in objects.balls.iter_mut().zip(self.balls.iter()) {
+            ball.pos = prev.pos.lerp(ball.pos, alpha);
+            ball.rotation = prev.rotation + (ball.rotation - prev.rotation) * alpha;
+        }
+        for (actuator, prev) in objects.actuators.iter_mut().zip(self.actuators.iter()) {
+            actuator.pos = prev.pos.lerp(actuator.pos, alpha);
+        }
+        for (hole, prev) in objects.holes.iter_mut().zip(self.holes.iter()) {
+            hole.pos = prev.pos.lerp(hole.pos, alpha);
+            if prev.is_open() && hole.is_open() {
+                hole.radius = prev.radius + (hole.radius - prev.radius) * alpha;
+            }
+        }
+        objects
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ball {
     pub pos: Vec3,
     pub vel: Vec3,
+    /// Angular velocity (rad/s)
+    pub spin: Vec3,
+    /// Accumulated rotation around the view axis, for drawing
+    pub rotation: f32,
     pub active: bool,
     pub forces: Vec<Vec3>,
     pub impulses: Vec<Vec3>,
+    pub angular_impulses: Vec<Vec3>,
     pub in_hole: Option<usize>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Actuator {
     pub pos: Vec2,
     pub vel: f32,
 }
 
+pub const SPAWN_HEIGHT: f32 = 20. / SCALE; // m above the rod
+
 impl Ball {
-    pub fn new() -> Self {
+    pub fn new(physics: &PhysicsProfile) -> Self {
         Ball {
             pos: Vec3::new(
                 SCREEN_W * 0.5 / SCALE,
-                (SCREEN_H - 120.) / SCALE,
-                BALL_RADIUS,
+                (SCREEN_H - 100.) / SCALE - SPAWN_HEIGHT,
+                physics.ball_radius,
             ),
-            vel: Vec3::new(0.0, 0.0, BALL_RADIUS),
+            vel: Vec3::new(0.0, 0.0, physics.ball_radius),
+            spin: Vec3::new(0.0, 0.0, 0.0),
+            rotation: 0.,
             active: true,
-            forces: vec![Vec3::from(GRAVITY)],
+            forces: vec![],
             impulses: vec![],
+            angular_impulses: vec![],
             in_hole: None,
         }
     }
+
+    /// Balls spread evenly around the middle of the rod
+    pub fn spawn(count: usize, physics: &PhysicsProfile) -> Vec<Ball> {
+        (0..count)
+            .map(|i| {
+                let mut ball = Ball::new(physics);
+                ball.pos.x += (i as f32 - (count - 1) as f32 / 2.) * physics.ball_radius * 3.;
+                ball
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GameLevelState {
+    #[serde(default = "level_version_v1")]
+    pub version: u32,
+    /// Empty in v1 files, filled in by `level_format::migrate`
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    /// Time to beat for a round, s
+    #[serde(default = "default_par_time")]
+    pub par_time: f32,
+    #[serde(default = "default_balls")]
+    pub balls: u16,
     pub background_image: String,
     pub goals: Vec<usize>,
     pub holes: Vec<Hole>,
+    /// Number of balls for each round, by goal index; unlisted rounds use one
+    #[serde(default)]
+    pub multiball: Vec<usize>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    /// Overrides for fields of the base physics profile
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub physics: Map<String, Value>,
     #[serde(skip)]
     pub level_file: String,
 }
 
+fn level_version_v1() -> u32 {
+    LEVEL_VERSION_V1
+}
+
+fn default_balls() -> u16 {
+    5
+}
+
+fn default_par_time() -> f32 {
+    60.
+}
+
 impl GameLevelState {
     pub fn new() -> Self {
         GameLevelState {
+            version: LEVEL_VERSION,
+            name: "new".to_string(),
+            author: String::new(),
+            difficulty: Difficulty::Normal,
+            par_time: default_par_time(),
+            balls: default_balls(),
             background_image: "level_example.png".to_string(),
             goals: Vec::new(),
             holes: Vec::new(),
+            multiball: Vec::new(),
+            obstacles: Vec::new(),
+            physics: Map::new(),
             level_file: "level_new.json".to_string(),
         }
     }
 
-    pub fn from_file(level_file: &str) -> Self {
-        let data = Asset::get(level_file)
-            .expect(&format!("Could not load level \"{}\"", level_file))
-            .data;
-        let data = serde_json::from_slice(&data).expect("Could not parse JSON");
+    /// Load, migrate and validate a level
+    pub fn from_file(level_file: &str) -> Result<Self> {
+        let level = Self::read(level_file)?;
+        let errors = level_format::validate(&level);
+        if !errors.is_empty() {
+            return Err(InvalidLevel {
+                level_file: level_file.to_string(),
+                errors,
+            }
+            .into());
+        }
+        Ok(level)
+    }
 
-        GameLevelState {
+    /// Load and migrate a level without validating it, e.g. for editing
+    pub fn read(level_file: &str) -> Result<Self> {
+        let data =
+            load_asset(level_file).ok_or(format!("Could not load level \"{}\"", level_file))?;
+        let level: GameLevelState = serde_json::from_slice(&data)?;
+        let level = level_format::migrate(GameLevelState {
             level_file: level_file.to_string(),
-            ..data
+            ..level
+        })
+        .map_err(|error| InvalidLevel {
+            level_file: level_file.to_string(),
+            errors: vec![error],
+        })?;
+        Ok(level)
+    }
+
+    /// Remove a hole, dropping goals on it and renumbering later goals
+    pub fn remove_hole(&mut self, index: usize) -> Hole {
+        let hole = self.holes.remove(index);
+        self.goals.retain(|goal| *goal != index);
+        for goal in self.goals.iter_mut().filter(|goal| **goal > index) {
+            *goal -= 1;
         }
+        hole
+    }
+}
+
+impl Default for GameLevelState {
+    fn default() -> Self {
+        GameLevelState::new()
     }
 }
 
@@ -287,10 +616,29 @@ pub struct GameCameraState {
 #[derive(Debug, PartialEq)]
 pub struct GameProgressState {
     pub goal_index: usize,
+    /// Simulated seconds, advanced only by the `dt` passed to the simulation
+    pub elapsed: f64,
     pub start_time: f64,
     pub score: u16,
     pub balls_left: u16,
     pub show_help: bool,
+    /// Position in the campaign, if the level is played as part of one
+    pub campaign_level: Option<usize>,
+    /// Score carried over from previous levels
+    pub start_score: u16,
+    /// Levels completed in this game
+    pub results: Vec<LevelResult>,
+    /// Factor for the time score of the current round
+    pub multiplier: u16,
+    /// Seconds deducted from the round clock by penalty holes
+    pub penalty_time: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelResult {
+    pub level_file: String,
+    pub name: String,
+    pub score: u16,
 }
 
 #[derive(Debug, PartialEq)]
@@ -326,17 +674,385 @@ impl DisplayState {
 
 impl GameProgressState {
     pub fn time(&self) -> f64 {
-        99. - f64::max(0., get_time() - self.start_time).round()
+        99. - (self.round_time() as f64 + self.penalty_time).round()
+    }
+
+    /// Seconds since the round started, after the grace period
+    pub fn round_time(&self) -> f32 {
+        f64::max(0., self.elapsed - self.start_time) as f32
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Hole {
     pub pos: Vec2,
     pub radius: f32,
+    #[serde(default, skip_serializing_if = "HoleShape::is_circle")]
+    pub shape: HoleShape,
+    /// Points the hole moves through after `pos`, then back again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<Vec2>,
+    /// Speed along `path`, m/s
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub speed: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timer: Option<HoleTimer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shrink: Option<HoleShrink>,
+    #[serde(default, skip_serializing_if = "HoleRole::is_normal")]
+    pub role: HoleRole,
+}
+
+/// What happens to a ball dropping into a hole that is not the goal
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HoleRole {
+    /// The ball is lost
+    #[default]
+    Normal,
+    /// Award `points`, the ball comes back
+    Bonus { points: u16 },
+    /// Deduct `seconds` from the round clock and `points` from the score,
+    /// the ball comes back
+    Penalty {
+        #[serde(default)]
+        seconds: f32,
+        #[serde(default)]
+        points: u16,
+    },
+    /// Award an extra ball, the ball comes back
+    ExtraBall,
+    /// Multiply the time score of the round by `factor`, the ball comes back
+    Multiplier { factor: u16 },
+}
+
+impl HoleRole {
+    pub fn is_normal(&self) -> bool {
+        *self == HoleRole::Normal
+    }
+
+    pub fn apply(&self, progress: &mut GameProgressState) {
+        match self {
+            HoleRole::Normal => {}
+            HoleRole::Bonus { points } => progress.score = progress.score.saturating_add(*points),
+            HoleRole::Penalty { seconds, points } => {
+                progress.penalty_time += *seconds as f64;
+                progress.score = progress.score.saturating_sub(*points);
+            }
+            HoleRole::ExtraBall => progress.balls_left += 1,
+            HoleRole::Multiplier { factor } => {
+                progress.multiplier = progress.multiplier.saturating_mul(*factor)
+            }
+        }
+    }
+}
+
+/// Hole that is open for `open` seconds, then closed for `closed` seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HoleTimer {
+    pub open: f32,
+    pub closed: f32,
+    /// Seconds into the cycle at the start of the round
+    #[serde(default)]
+    pub offset: f32,
+}
+
+/// Hole that loses `rate` m of radius per second, down to `min_radius`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HoleShrink {
+    pub rate: f32,
+    pub min_radius: f32,
+}
+
+fn is_zero(value: &f32) -> bool {
+    *value == 0.
+}
+
+impl Hole {
+    /// The hole `t` seconds into the round; a closed hole has no radius
+    pub fn at(&self, t: f32) -> Hole {
+        let mut hole = self.clone();
+        hole.pos = self.path_pos(t * self.speed);
+        if let Some(shrink) = &self.shrink {
+            hole.radius = f32::max(self.radius - shrink.rate * t, shrink.min_radius);
+        }
+        if let Some(timer) = &self.timer {
+            if (t + timer.offset).rem_euclid(timer.open + timer.closed) >= timer.open {
+                hole.radius = 0.;
+            }
+        }
+        hole
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.radius > 0.
+    }
+
+    pub fn contains(&self, pos: Vec2) -> bool {
+        self.shape.contains(pos - self.pos, self.radius)
+    }
+
+    /// Point on the rim nearest to `pos` and the outward normal there
+    pub fn rim(&self, pos: Vec2) -> (Vec2, Vec2) {
+        let (edge, normal) = self.shape.rim(pos - self.pos, self.radius);
+        (self.pos + edge, normal)
+    }
+
+    pub fn outline(&self) -> Vec<Vec2> {
+        let outline = self.shape.outline(self.radius);
+        outline.into_iter().map(|point| self.pos + point).collect()
+    }
+
+    pub fn overlaps(&self, other: &Hole) -> bool {
+        match (&self.shape, &other.shape) {
+            (HoleShape::Circle, HoleShape::Circle) => {
+                self.pos.distance(other.pos) < self.radius + other.radius
+            }
+            _ => {
+                self.outline()
+                    .into_iter()
+                    .any(|point| other.contains(point))
+                    || other
+                        .outline()
+                        .into_iter()
+                        .any(|point| self.contains(point))
+            }
+        }
+    }
+
+    /// Copies of the hole along its path, at most `step` m apart
+    pub fn swept(&self, step: f32) -> Vec<Hole> {
+        let length: f32 = std::iter::once(&self.pos)
+            .chain(self.path.iter())
+            .zip(self.path.iter())
+            .map(|(a, b)| a.distance(*b))
+            .sum();
+        let samples = (length / step).ceil() as usize;
+        (0..=samples)
+            .map(|i| Hole {
+                pos: self.path_pos(length * i as f32 / samples.max(1) as f32),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Position after moving `distance` along the path and back
+    fn path_pos(&self, distance: f32) -> Vec2 {
+        let points: Vec<Vec2> = std::iter::once(self.pos)
+            .chain(self.path.iter().copied())
+            .collect();
+        let length: f32 = points.windows(2).map(|w| w[0].distance(w[1])).sum();
+        if length <= 0. {
+            return self.pos;
+        }
+        let mut distance = distance.rem_euclid(2. * length);
+        if distance > length {
+            distance = 2. * length - distance;
+        }
+        for w in points.windows(2) {
+            let segment = w[0].distance(w[1]);
+            if distance <= segment && segment > 0. {
+                return w[0].lerp(w[1], distance / segment);
+            }
+            distance -= segment;
+        }
+        *points.last().unwrap_or(&self.pos)
+    }
+}
+
+pub const WALL_THICKNESS: f32 = 0.012; // m
+pub const BUMPER_KICK: f32 = 0.8; // m/s
+
+/// Static level element the ball collides with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Obstacle {
+    Post {
+        pos: Vec2,
+        radius: f32,
+    },
+    /// Post that kicks the ball away with `kick` m/s
+    Bumper {
+        pos: Vec2,
+        radius: f32,
+        kick: f32,
+    },
+    /// Straight wall between two points
+    Wall {
+        from: Vec2,
+        to: Vec2,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObstacleKind {
+    Post,
+    Bumper,
+    Wall,
+}
+
+impl Obstacle {
+    /// Point on the obstacle's center line or center nearest to `pos`,
+    /// with the obstacle's radius around it
+    pub fn nearest(&self, pos: Vec2) -> (Vec2, f32) {
+        match self {
+            Obstacle::Post {
+                pos: center,
+                radius,
+            }
+            | Obstacle::Bumper {
+                pos: center,
+                radius,
+                ..
+            } => (*center, *radius),
+            Obstacle::Wall { from, to } => {
+                let line = *to - *from;
+                let t = match line.length_squared() {
+                    l if l > 0. => ((pos - *from).dot(line) / l).clamp(0., 1.),
+                    _ => 0.,
+                };
+                (*from + line * t, WALL_THICKNESS / 2.)
+            }
+        }
+    }
+
+    pub fn contains(&self, pos: Vec2) -> bool {
+        let (nearest, radius) = self.nearest(pos);
+        nearest.distance(pos) < radius.max(WALL_THICKNESS)
+    }
+
+    pub fn translated(&self, delta: Vec2) -> Obstacle {
+        match self.clone() {
+            Obstacle::Post { pos, radius } => Obstacle::Post {
+                pos: pos + delta,
+                radius,
+            },
+            Obstacle::Bumper { pos, radius, kick } => Obstacle::Bumper {
+                pos: pos + delta,
+                radius,
+                kick,
+            },
+            Obstacle::Wall { from, to } => Obstacle::Wall {
+                from: from + delta,
+                to: to + delta,
+            },
+        }
+    }
+
+    /// Points that have to lie on the playfield
+    pub fn points(&self) -> Vec<Vec2> {
+        match self {
+            Obstacle::Post { pos, .. } | Obstacle::Bumper { pos, .. } => vec![*pos],
+            Obstacle::Wall { from, to } => vec![*from, *to],
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct EditorState {
+    pub tool: EditorTool,
+    /// Radius for new holes
     pub radius: f32,
+    /// Shape for new holes
+    pub shape: HoleShape,
+    pub selected: Option<usize>,
+    /// Position of the selected hole when dragging started,
+    /// or of the mouse for obstacles and new walls
+    pub drag_from: Option<Vec2>,
+    /// Selected hole's centre relative to the mouse when dragging started
+    pub drag_offset: Vec2,
+    /// Selected obstacle as it was when dragging started
+    pub drag_obstacle: Option<Obstacle>,
+    /// Kind of obstacle placed by clicking
+    pub obstacle: ObstacleKind,
+    pub grid: bool,
+    /// Last save error, shown in the editor header
+    pub message: Option<String>,
+    pub history: History,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EditorTool {
+    Holes,
+    Goals,
+    Obstacles,
+}
+
+impl EditorState {
+    pub fn new() -> Self {
+        EditorState {
+            tool: EditorTool::Holes,
+            radius: 0.052,
+            shape: HoleShape::Circle,
+            selected: None,
+            drag_from: None,
+            drag_offset: Vec2::new(0., 0.),
+            drag_obstacle: None,
+            obstacle: ObstacleKind::Post,
+            grid: false,
+            message: None,
+            history: History::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InitialsState {
+    pub pending: Vec<PendingScore>,
+    pub letters: [u8; 3],
+    pub position: usize,
+}
+
+impl InitialsState {
+    pub fn new(pending: Vec<PendingScore>) -> Self {
+        InitialsState {
+            pending,
+            letters: [0; 3],
+            position: 0,
+        }
+    }
+
+    pub fn initials(&self) -> String {
+        self.letters
+            .iter()
+            .map(|letter| (b'A' + letter) as char)
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HighScoreState {
+    pub scores: HighScores,
+    pub selected: usize,
+}
+
+impl HighScoreState {
+    /// Show the table for `key`, or the first table
+    pub fn new(scores: HighScores, key: Option<&str>) -> Self {
+        let selected = key
+            .and_then(|key| scores.tables.keys().position(|k| k == key))
+            .unwrap_or(0);
+        HighScoreState { scores, selected }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ControlsState {
+    pub settings: Settings,
+    /// Row of the action being edited; the rows after the actions are reset and back
+    pub selected: usize,
+    /// Waiting for a key to bind to the selected action
+    pub waiting: bool,
+    pub message: Option<String>,
+}
+
+impl ControlsState {
+    pub fn new(settings: Settings) -> Self {
+        ControlsState {
+            settings,
+            selected: 0,
+            waiting: false,
+            message: None,
+        }
+    }
 }
@@ -0,0 +1,72 @@
+pub const FIXED_DELTATIME: f32 = 0.008;
+// Upper bound for a single frame, so a stall does not trigger a burst of steps
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// Accumulates frame time and releases it in fixed physics steps,
+/// so the simulation does not depend on the frame rate.
+pub struct Timestep {
+    accumulator: f32,
+}
+
+impl Timestep {
+    pub fn new() -> Self {
+        Timestep { accumulator: 0. }
+    }
+
+    /// Add the frame time and return how many fixed steps to run
+    pub fn advance(&mut self, frame_time: f32) -> i32 {
+        self.accumulator += frame_time.min(MAX_FRAME_TIME);
+        let steps = (self.accumulator / FIXED_DELTATIME).floor();
+        self.accumulator -= steps * FIXED_DELTATIME;
+        steps as i32
+    }
+
+    /// Put steps that were not run back into the accumulator, e.g. when
+    /// an event ended the frame before all of them ran
+    pub fn give_back(&mut self, steps: i32) {
+        self.accumulator += steps as f32 * FIXED_DELTATIME;
+    }
+
+    /// Fraction of a step left in the accumulator, for interpolating rendering
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / FIXED_DELTATIME).clamp(0., 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Steps run and the rendering fractions seen over `frames` frames
+    fn run(frame_time: f32, frames: usize) -> (i32, Vec<f32>) {
+        let mut timestep = Timestep::new();
+        let mut alphas = vec![];
+        let steps = (0..frames)
+            .map(|_| {
+                let steps = timestep.advance(frame_time);
+                alphas.push(timestep.alpha());
+                steps
+            })
+            .sum();
+        (steps, alphas)
+    }
+
+    #[test]
+    fn steps_do_not_depend_on_frame_rate() {
+        // Ten seconds at 30 and at 144 frames per second
+        let (slow_steps, slow_alphas) = run(1. / 30., 300);
+        let (fast_steps, fast_alphas) = run(1. / 144., 1440);
+        assert_eq!(slow_steps, fast_steps);
+        for alpha in slow_alphas.into_iter().chain(fast_alphas) {
+            assert!((0. ..1.).contains(&alpha), "alpha {}", alpha);
+        }
+    }
+
+    #[test]
+    fn give_back_runs_steps_in_the_next_frame() {
+        let mut timestep = Timestep::new();
+        assert_eq!(timestep.advance(FIXED_DELTATIME * 3.5), 3);
+        timestep.give_back(2);
+        assert_eq!(timestep.advance(0.), 2);
+    }
+}
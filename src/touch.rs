@@ -0,0 +1,108 @@
+use crate::config::{SCREEN_H, SCREEN_W};
+use macroquad::prelude::*;
+
+// Drag distance for full actuator speed, as a fraction of the screen height
+const SLIDER_RANGE: f32 = 0.12;
+// Horizontal position of each slider, as a fraction of the screen width
+const SLIDER_X: [f32; 2] = [0.25, 0.75];
+// Distance from a slider where presses show the sliders, as a fraction of the screen width
+const SLIDER_AREA: f32 = 0.15;
+// Touch id for dragging with the mouse
+const MOUSE_ID: u64 = u64::MAX;
+
+/// On-screen sliders: a drag on the left or right half of the screen moves
+/// that side's actuator in proportion to how far it is from where it started
+pub struct TouchSliders {
+    drags: Vec<Drag>,
+    /// A touch or drag started on a slider, so the sliders are shown
+    pub used: bool,
+}
+
+/// Drag in world coordinates
+#[derive(Debug, Clone)]
+struct Drag {
+    id: u64,
+    side: usize,
+    origin: Vec2,
+    pos: Vec2,
+}
+
+impl TouchSliders {
+    pub fn new() -> Self {
+        TouchSliders {
+            drags: vec![],
+            used: false,
+        }
+    }
+
+    /// Follow touches and the mouse, returning the value of each slider being dragged
+    pub fn update(&mut self, camera: &Camera2D) -> [Option<f32>; 2] {
+        let mut current: Vec<(u64, Vec2)> = touches()
+            .iter()
+            .filter(|touch| !matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled))
+            .map(|touch| (touch.id, camera.screen_to_world(touch.position)))
+            .collect();
+        // Touches also move the mouse, only use it without them
+        if current.is_empty() && is_mouse_button_down(MouseButton::Left) {
+            current.push((MOUSE_ID, camera.screen_to_world(mouse_position().into())));
+        }
+
+        self.drags
+            .retain(|drag| current.iter().any(|(id, _)| *id == drag.id));
+        for (id, pos) in current {
+            match self.drags.iter_mut().find(|drag| drag.id == id) {
+                Some(drag) => drag.pos = pos,
+                None => {
+                    // Clicks elsewhere, like on menus or in the editor, don't count
+                    self.used |= in_slider_area(pos);
+                    self.drags.push(Drag {
+                        id,
+                        side: (pos.x > SCREEN_W / 2.) as usize,
+                        origin: pos,
+                        pos,
+                    });
+                }
+            }
+        }
+
+        let mut values = [None, None];
+        for drag in self.drags.iter() {
+            let travel = (drag.origin.y - drag.pos.y) / (SCREEN_H * SLIDER_RANGE);
+            // The first finger on a side controls it
+            values[drag.side].get_or_insert(travel.clamp(-1., 1.));
+        }
+        values
+    }
+}
+
+pub fn draw_sliders(sliders: &TouchSliders) {
+    if !sliders.used {
+        return;
+    }
+    let color = Color::new(1., 1., 1., 0.15);
+    for x in SLIDER_X {
+        draw_line(
+            SCREEN_W * x,
+            SCREEN_H * 0.2,
+            SCREEN_W * x,
+            SCREEN_H * 0.8,
+            6.,
+            color,
+        );
+    }
+    for drag in sliders.drags.iter() {
+        let x = SCREEN_W * SLIDER_X[drag.side];
+        let range = SCREEN_H * SLIDER_RANGE;
+        let origin = drag.origin.y;
+        let travel = (drag.pos.y - origin).clamp(-range, range);
+        draw_circle_lines(x, origin, 40., 4., color);
+        draw_circle(x, origin + travel, 30., Color::new(1., 1., 1., 0.4));
+    }
+}
+
+fn in_slider_area(pos: Vec2) -> bool {
+    let near = SLIDER_X
+        .iter()
+        .any(|x| (pos.x - SCREEN_W * x).abs() < SCREEN_W * SLIDER_AREA);
+    near && pos.y > SCREEN_H * 0.2 && pos.y < SCREEN_H * 0.8
+}